@@ -27,7 +27,14 @@
           entr
           rustfmt
           clippy
+          pkg-config
         ];
+
+        # For the crypt feature.
+        buildInputs = with pkgs; [
+          cryptsetup
+        ];
+        LIBCLANG_PATH = "${pkgs.libclang.lib}/lib";
       });
     };
 }
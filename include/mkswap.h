@@ -0,0 +1,93 @@
+#ifndef MKSWAP_H
+#define MKSWAP_H
+
+/* Generated by cbindgen from src/ffi.rs: do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * What `mkswap_probe` found.
+ */
+typedef enum MkswapVersion {
+  /**
+   * No swap signature.
+   */
+  MKSWAP_VERSION_NONE = 0,
+  /**
+   * A legacy `SWAP-SPACE` swap space.
+   */
+  MKSWAP_VERSION_V0 = 1,
+  /**
+   * A current `SWAPSPACE2` swap space.
+   */
+  MKSWAP_VERSION_V1 = 2,
+} MkswapVersion;
+
+/**
+ * The options of `mkswap_write`. A zeroed struct writes a swap space with
+ * the defaults.
+ */
+typedef struct MkswapOptions {
+  /**
+   * The page size in bytes, or 0 for the running system's.
+   */
+  uint64_t page_size;
+  /**
+   * The size of the swap space in bytes, or 0 for the whole file or
+   * device.
+   */
+  uint64_t size;
+  /**
+   * The label, as a NUL-terminated UTF-8 string, or NULL for none.
+   */
+  const char *label;
+  /**
+   * The UUID, or all zeros for a random one.
+   */
+  uint8_t uuid[16];
+  /**
+   * Skip the safety checks refusing to overwrite a swap space, mounted
+   * device or other signature in use.
+   */
+  bool force;
+} MkswapOptions;
+
+/**
+ * Write a swap space to the open file or device `fd`, like
+ * `SwapWriter::write_file`. `options` may be NULL for the defaults.
+ *
+ * Returns 0 on success, or -1 with the error in `mkswap_last_error`. The
+ * file descriptor is left open.
+ *
+ * # Safety
+ *
+ * `fd` must be open for reading and writing, and `options` must be NULL
+ * or point to a valid `MkswapOptions`.
+ */
+int mkswap_write(int fd, const struct MkswapOptions *options);
+
+/**
+ * Check whether the open file or device `fd` contains a swap signature,
+ * like `probe`.
+ *
+ * Returns a `MkswapVersion`, or -1 with the error in `mkswap_last_error`.
+ * The file descriptor is left open.
+ *
+ * # Safety
+ *
+ * `fd` must be open for reading.
+ */
+int mkswap_probe(int fd);
+
+/**
+ * The message of the last error on this thread, or NULL if there was none.
+ *
+ * The string is valid until the next failing call on this thread.
+ */
+const char *mkswap_last_error(void);
+
+#endif  /* MKSWAP_H */
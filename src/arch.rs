@@ -0,0 +1,171 @@
+//! The limits the kernel of each architecture puts on a swap space, and the
+//! header format it expects.
+
+use crate::Endianness;
+
+/// An architecture a swap space can be written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    /// 64-bit x86.
+    X86_64,
+    /// 64-bit ARM.
+    Aarch64,
+    /// 32-bit ARM.
+    Arm,
+    /// 64-bit RISC-V.
+    Riscv64,
+    /// 64-bit IBM Z.
+    S390x,
+}
+
+impl Arch {
+    /// The architecture this code was compiled for, if it's a known one.
+    pub fn current() -> Option<Self> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Arch::X86_64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Arch::Aarch64)
+        } else if cfg!(target_arch = "arm") {
+            Some(Arch::Arm)
+        } else if cfg!(target_arch = "riscv64") {
+            Some(Arch::Riscv64)
+        } else if cfg!(target_arch = "s390x") {
+            Some(Arch::S390x)
+        } else {
+            None
+        }
+    }
+
+    /// The name Rust and the kernel's uname use for this architecture.
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+            Arch::Arm => "arm",
+            Arch::Riscv64 => "riscv64",
+            Arch::S390x => "s390x",
+        }
+    }
+
+    /// How many bits of a swap entry hold the page offset.
+    ///
+    /// Swapped out pages are recorded in page table entries, which have only
+    /// so many bits left for the offset after the swap type and the bits the
+    /// hardware reserves. These are the `__SWP_OFFSET_BITS` of each
+    /// architecture's asm/pgtable.h.
+    fn swap_offset_bits(self) -> u32 {
+        match self {
+            Arch::X86_64 => 50,
+            Arch::Aarch64 => 50,
+            Arch::Arm => 25,
+            Arch::Riscv64 => 53,
+            Arch::S390x => 52,
+        }
+    }
+}
+
+/// A common kernel configuration to write a swap space for, setting the
+/// page size and endianness together with [`SwapWriter::target`](crate::SwapWriter::target).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetProfile {
+    /// x86_64, with 4 KiB pages.
+    X86_64,
+    /// aarch64 with 4 KiB pages, as in most distributions.
+    Aarch64_4K,
+    /// aarch64 with 16 KiB pages, as on Apple silicon.
+    Aarch64_16K,
+    /// aarch64 with 64 KiB pages, as in some server distributions.
+    Aarch64_64K,
+    /// Little endian ppc64, with 64 KiB pages.
+    Ppc64le,
+    /// Big endian ppc64, with 64 KiB pages.
+    Ppc64,
+    /// s390x, with 4 KiB pages.
+    S390x,
+    /// riscv64, with 4 KiB pages.
+    Riscv64,
+}
+
+impl TargetProfile {
+    /// The page size of the target's kernel.
+    pub fn page_size(self) -> u64 {
+        match self {
+            TargetProfile::X86_64
+            | TargetProfile::Aarch64_4K
+            | TargetProfile::S390x
+            | TargetProfile::Riscv64 => 4096,
+            TargetProfile::Aarch64_16K => 16384,
+            TargetProfile::Aarch64_64K | TargetProfile::Ppc64le | TargetProfile::Ppc64 => 65536,
+        }
+    }
+
+    /// The byte order of the target.
+    pub fn endianness(self) -> Endianness {
+        match self {
+            TargetProfile::Ppc64 | TargetProfile::S390x => Endianness::Big,
+            _ => Endianness::Little,
+        }
+    }
+
+    /// The target's architecture, if its limits are known to [`max_swap_bytes`].
+    pub fn arch(self) -> Option<Arch> {
+        match self {
+            TargetProfile::X86_64 => Some(Arch::X86_64),
+            TargetProfile::Aarch64_4K | TargetProfile::Aarch64_16K | TargetProfile::Aarch64_64K => {
+                Some(Arch::Aarch64)
+            }
+            TargetProfile::S390x => Some(Arch::S390x),
+            TargetProfile::Riscv64 => Some(Arch::Riscv64),
+            TargetProfile::Ppc64le | TargetProfile::Ppc64 => None,
+        }
+    }
+}
+
+/// What [`SwapWriter::arch_limit`](crate::SwapWriter::arch_limit) does when
+/// the swap space is larger than the kernel can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchLimitPolicy {
+    /// Write the swap space, and say so in
+    /// [`WriteSummary::warnings`](crate::WriteSummary::warnings). The kernel
+    /// truncates it when it's enabled.
+    Warn,
+    /// Refuse to write the swap space with
+    /// [`Error::ExceedsArchLimit`](crate::Error::ExceedsArchLimit).
+    Error,
+}
+
+/// The largest swap space, in bytes, which the kernel of an architecture
+/// can use with a given page size.
+///
+/// The kernel truncates larger swap spaces when enabling them. This is
+/// often well past what the header can describe: see
+/// [`ClampPolicy`](crate::ClampPolicy). On x86_64 the kernel may also lower
+/// the limit to mitigate L1TF, depending on the CPU.
+pub fn max_swap_bytes(page_size: u64, arch: Arch) -> u64 {
+    (1u64 << arch.swap_offset_bits()).saturating_mul(page_size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        assert_eq!(max_swap_bytes(4096, Arch::Arm), 128 * 1024 * 1024 * 1024);
+        assert_eq!(max_swap_bytes(4096, Arch::X86_64), 1 << 62);
+        assert_eq!(max_swap_bytes(65536, Arch::Riscv64), u64::MAX);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn target() {
+        let mut buffer = std::io::Cursor::new(vec![0; 16 * 65536]);
+        crate::SwapWriter::new()
+            .target(TargetProfile::Ppc64)
+            .write(&mut buffer)
+            .unwrap();
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[1024..1028], &1u32.to_be_bytes());
+        assert_eq!(&bytes[65536 - 10..65536], b"SWAPSPACE2");
+    }
+}
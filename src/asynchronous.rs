@@ -0,0 +1,166 @@
+//! Write swap spaces to the async handles of tokio or futures-io.
+
+use std::io::SeekFrom;
+
+use crate::header::field_error;
+use crate::{Error, Header, SwapWriter, WriteSummary};
+
+/// Write the header of an AsyncSwapWriter to a handle, given the extension
+/// traits of tokio or futures-io in scope, which have the same methods.
+macro_rules! write_header {
+    ($self:ident, $handle:ident) => {{
+        let offset = $self.writer.offset;
+        let detected = $handle.seek(SeekFrom::End(0)).await;
+        let (header, sync, summary) = $self.prepare(detected)?;
+
+        for &(start, end) in &header.regions {
+            $handle
+                .seek(SeekFrom::Start(offset + start))
+                .await
+                .map_err(Error::WriteHeader)?;
+            let mut position = start;
+            while position < end {
+                let error = match $handle
+                    .write(&header.page[position as usize..end as usize])
+                    .await
+                {
+                    Ok(0) => std::io::ErrorKind::WriteZero.into(),
+                    Ok(written) => {
+                        position += written as u64;
+                        continue;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => e,
+                };
+                return Err(field_error(
+                    header.version,
+                    &header.page,
+                    position,
+                    offset,
+                    error,
+                ));
+            }
+        }
+        $handle
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(Error::WriteHeader)?;
+        if sync {
+            $handle.flush().await.map_err(Error::WriteHeader)?;
+        }
+
+        Ok(summary)
+    }};
+}
+
+/// Writes the swap space configured by a [`SwapWriter`] to an
+/// `AsyncWrite + AsyncSeek` handle, like a `tokio::fs::File` or an
+/// `async_std::fs::File`.
+///
+/// The header is built in memory like [`SwapWriter::write`] builds it, and
+/// only the writes are async. Progress reports and cancellation aren't
+/// supported, since writing a header involves neither.
+pub struct AsyncSwapWriter {
+    writer: SwapWriter,
+}
+
+impl AsyncSwapWriter {
+    /// Construct a new AsyncSwapWriter, writing what `writer` would.
+    ///
+    /// Any progress or cancellation callbacks of the writer are dropped.
+    pub fn new(mut writer: SwapWriter) -> Self {
+        writer.progress = None;
+        writer.should_continue = None;
+
+        Self { writer }
+    }
+
+    /// Write the configured swap space out to a tokio handle.
+    ///
+    /// This behaves like [`SwapWriter::write`], including writing from its
+    /// [`offset`](SwapWriter::offset).
+    #[cfg(feature = "tokio")]
+    pub async fn write<T>(self, mut handle: T) -> Result<WriteSummary, Error>
+    where
+        T: tokio::io::AsyncWrite + tokio::io::AsyncSeek + Unpin,
+    {
+        use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+        write_header!(self, handle)
+    }
+
+    /// Write the configured swap space out to a futures-io handle, as
+    /// [`AsyncSwapWriter::write`] does for tokio.
+    #[cfg(feature = "futures")]
+    pub async fn write_futures<T>(self, mut handle: T) -> Result<WriteSummary, Error>
+    where
+        T: futures_util::io::AsyncWrite + futures_util::io::AsyncSeek + Unpin,
+    {
+        use futures_util::io::{AsyncSeekExt, AsyncWriteExt};
+
+        write_header!(self, handle)
+    }
+
+    /// Build the header, given where the end of the handle was found, and
+    /// whether to flush it after writing.
+    fn prepare(self, end: std::io::Result<u64>) -> Result<(Header, bool, WriteSummary), Error> {
+        let writer = self.writer;
+        let page_size = writer.resolve_page_size()?;
+        let detected = end.map(|end| end.saturating_sub(writer.offset));
+        let (layout, header) = writer.prepare(page_size, detected)?;
+        let sync = writer.sync;
+        let summary = writer.summary(header.uuid, layout);
+
+        Ok((header, sync, summary))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "tokio")]
+    use std::io::Cursor;
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn write_async() {
+        let mut buffer = Cursor::new(vec![0xff; 40 * 1024]);
+        let writer = SwapWriter::new()
+            .page_size(4096)
+            .label("async".into())
+            .unwrap();
+        let summary = AsyncSwapWriter::new(writer)
+            .write(&mut buffer)
+            .await
+            .unwrap();
+        assert_eq!(summary.pages, 10);
+
+        let mut expected = Cursor::new(vec![0xff; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .label("async".into())
+            .unwrap()
+            .uuid(summary.uuid.unwrap())
+            .write(&mut expected)
+            .unwrap();
+        assert_eq!(buffer.into_inner(), expected.into_inner());
+
+        fn is_send<T: Send>(_: T) {}
+        is_send(AsyncSwapWriter::new(SwapWriter::new()).write(Cursor::new(Vec::new())));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn write_futures() {
+        let mut buffer = futures_util::io::Cursor::new(vec![0; 80 * 1024]);
+        let writer = SwapWriter::new().page_size(4096).offset(40 * 1024);
+        let summary =
+            futures_executor::block_on(AsyncSwapWriter::new(writer).write_futures(&mut buffer))
+                .unwrap();
+        assert_eq!(summary.pages, 10);
+
+        let bytes = buffer.into_inner();
+        assert!(bytes[..40 * 1024].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[44 * 1024 - 10..44 * 1024], b"SWAPSPACE2");
+    }
+}
@@ -0,0 +1,183 @@
+//! Back up and restore the regions of a device which formatting overwrites.
+
+use std::io::{ErrorKind, Read, SeekFrom, Write};
+use std::ops::Range;
+
+use crate::{detect_signatures, Error, ReadSeek};
+
+/// The magic bytes starting a backup written by [`backup`].
+const BACKUP_MAGIC: &[u8; 8] = b"MKSWBAK1";
+
+/// Save the regions a new swap space would overwrite, so they can be put
+/// back with [`restore`].
+///
+/// This is the first page, which holds the bootbits, the swap header and
+/// the swap magic, and the magic bytes of every signature found by
+/// [`detect_signatures`] past it, which [`wipe_signatures`](crate::wipe_signatures)
+/// zeroes. Regions past the end of the handle are saved up to its end.
+///
+/// The backup is a short magic, followed by each region's offset and
+/// length as little-endian u64s, and the region's bytes.
+///
+/// Returns the regions which were saved.
+pub fn backup<T: ReadSeek, W: Write>(
+    mut handle: T,
+    page_size: u64,
+    mut backup: W,
+) -> Result<Vec<Range<u64>>, Error> {
+    let signatures = detect_signatures(&mut handle)?
+        .into_iter()
+        .map(|found| found.offset..found.offset + found.length as u64)
+        .filter(|region| region.end > page_size);
+    let regions: Vec<_> = std::iter::once(0..page_size).chain(signatures).collect();
+
+    backup.write_all(BACKUP_MAGIC).map_err(Error::Backup)?;
+    let mut saved = Vec::with_capacity(regions.len());
+    for region in regions {
+        handle
+            .seek(SeekFrom::Start(region.start))
+            .map_err(Error::ReadHeader)?;
+        let mut bytes = Vec::new();
+        (&mut handle)
+            .take(region.end - region.start)
+            .read_to_end(&mut bytes)
+            .map_err(Error::ReadHeader)?;
+        if bytes.is_empty() {
+            continue;
+        }
+
+        backup
+            .write_all(&region.start.to_le_bytes())
+            .map_err(Error::Backup)?;
+        backup
+            .write_all(&(bytes.len() as u64).to_le_bytes())
+            .map_err(Error::Backup)?;
+        backup.write_all(&bytes).map_err(Error::Backup)?;
+        saved.push(region.start..region.start + bytes.len() as u64);
+    }
+    backup.flush().map_err(Error::Backup)?;
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    Ok(saved)
+}
+
+/// Write the regions saved by [`backup`] back at their original offsets,
+/// undoing a format.
+///
+/// Each region is read back after writing, and [`Error::RestoreMismatch`]
+/// is returned with its offset if it doesn't match the backup.
+///
+/// Returns the regions which were restored.
+pub fn restore<T: ReadSeek + Write, R: Read>(
+    mut handle: T,
+    mut backup: R,
+) -> Result<Vec<Range<u64>>, Error> {
+    let mut magic = [0; 8];
+    read_backup(&mut backup, &mut magic)?;
+    if &magic != BACKUP_MAGIC {
+        return Err(Error::InvalidBackup);
+    }
+
+    let mut restored = Vec::new();
+    loop {
+        let mut offset = [0; 8];
+        match backup.read_exact(&mut offset) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(Error::Backup(e)),
+        }
+        let offset = u64::from_le_bytes(offset);
+        let mut length = [0; 8];
+        read_backup(&mut backup, &mut length)?;
+        let length = u64::from_le_bytes(length);
+        let mut bytes = Vec::new();
+        (&mut backup)
+            .take(length)
+            .read_to_end(&mut bytes)
+            .map_err(Error::Backup)?;
+        if bytes.len() as u64 != length {
+            return Err(Error::InvalidBackup);
+        }
+
+        handle
+            .seek(SeekFrom::Start(offset))
+            .map_err(Error::WriteHeader)?;
+        handle.write_all(&bytes).map_err(Error::WriteHeader)?;
+        handle.flush().map_err(Error::WriteHeader)?;
+
+        let mut written = vec![0; bytes.len()];
+        handle
+            .seek(SeekFrom::Start(offset))
+            .map_err(Error::ReadHeader)?;
+        handle.read_exact(&mut written).map_err(Error::ReadHeader)?;
+        if written != bytes {
+            return Err(Error::RestoreMismatch(offset));
+        }
+
+        restored.push(offset..offset + length);
+    }
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    Ok(restored)
+}
+
+/// Read exactly enough bytes from a backup, which is invalid if truncated.
+fn read_backup<R: Read>(mut backup: R, buffer: &mut [u8]) -> Result<(), Error> {
+    backup.read_exact(buffer).map_err(|e| match e.kind() {
+        ErrorKind::UnexpectedEof => Error::InvalidBackup,
+        _ => Error::Backup(e),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn backup_regions() {
+        let mut buffer = Cursor::new(vec![0xaa; 16 * 8192]);
+        SwapWriter::new()
+            .page_size(8192)
+            .write(&mut buffer)
+            .unwrap();
+
+        let mut saved = Vec::new();
+        let regions = backup(&mut buffer, 4096, &mut saved).unwrap();
+        assert_eq!(regions, vec![0..4096, 8182..8192]);
+        assert_eq!(&saved[..8], BACKUP_MAGIC);
+        assert_eq!(saved.len(), 8 + 16 + 4096 + 16 + 10);
+        assert_eq!(&saved[24..24 + 4096], &buffer.get_ref()[..4096]);
+
+        let mut saved = Vec::new();
+        let regions = backup(Cursor::new(vec![0; 100]), 4096, &mut saved).unwrap();
+        assert_eq!(regions, vec![0..100]);
+    }
+
+    #[test]
+    fn restore_regions() {
+        let original = vec![0xaa; 16 * 8192];
+        let mut buffer = Cursor::new(original.clone());
+        let mut saved = Vec::new();
+        backup(&mut buffer, 4096, &mut saved).unwrap();
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        assert_ne!(buffer.get_ref(), &original);
+
+        let restored = restore(&mut buffer, &saved[..]).unwrap();
+        assert_eq!(restored, vec![0..4096]);
+        assert_eq!(buffer.get_ref(), &original);
+
+        assert!(matches!(
+            restore(&mut buffer, &saved[..30]),
+            Err(Error::InvalidBackup)
+        ));
+        assert!(matches!(
+            restore(&mut buffer, &b"not a backup"[..]),
+            Err(Error::InvalidBackup)
+        ));
+    }
+}
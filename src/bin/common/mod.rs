@@ -0,0 +1,36 @@
+//! Helpers shared by the command line tools.
+
+/// Split `--flag=value` and `-fvalue` into the flag and its attached value.
+pub fn split_flag(arg: &str) -> (String, Option<String>) {
+    if let Some(long) = arg.strip_prefix("--") {
+        match long.split_once('=') {
+            Some((flag, value)) => (format!("--{}", flag), Some(value.to_string())),
+            None => (arg.to_string(), None),
+        }
+    } else if let Some((split, _)) = arg.char_indices().nth(2).filter(|_| arg.starts_with('-')) {
+        (arg[..split].to_string(), Some(arg[split..].to_string()))
+    } else {
+        (arg.to_string(), None)
+    }
+}
+
+/// Check the value [`split_flag`] attached to a flag which takes none. A
+/// short flag's is more short flags bundled with it, like the `q` of `-fq`,
+/// which are queued in `pending` to be parsed next; a long flag's is an
+/// error.
+pub fn no_value(
+    flag: &str,
+    attached: Option<&str>,
+    pending: &mut Option<String>,
+) -> Result<(), String> {
+    match attached {
+        None => Ok(()),
+        Some(_) if flag.starts_with("--") => {
+            Err(format!("option '{}' doesn't allow an argument", flag))
+        }
+        Some(bundled) => {
+            *pending = Some(format!("-{}", bundled));
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! A pure-Rust `mkswap`, compatible with the util-linux flags.
+
+use std::process::ExitCode;
+
+use mkswap::{
+    device_size, open_device, parse_size, BootBits, ClampPolicy, LockMode, SwapReader, SwapVersion,
+    SwapWriter, UuidSpec,
+};
+
+mod common;
+use common::{no_value, split_flag};
+
+const USAGE: &str = "\
+Usage:
+ mkswap [options] device [size]
+
+Set up a Linux swap area.
+
+Options:
+ -c, --check               check bad blocks before creating the swap area
+ -f, --force               allow swap size area be larger than device
+ -q, --quiet               suppress output and warning messages
+ -p, --pagesize SIZE       specify page size in bytes
+ -L, --label LABEL         specify label
+ -v, --swapversion NUM     specify swap-space version number
+ -U, --uuid UUID           specify the uuid to use (or clear, random or time)
+     --lock[=<mode>]       use exclusive device lock (yes, no or nonblock)
+     --json                print the result as JSON, verifying the header
+ -h, --help                display this help
+ -V, --version             display version
+";
+
+struct Options {
+    check: bool,
+    force: bool,
+    quiet: bool,
+    json: bool,
+    page_size: Option<u64>,
+    label: Option<String>,
+    version: SwapVersion,
+    uuid: Option<UuidSpec>,
+    /// How to lock the device while writing to it, if at all.
+    lock: Option<LockMode>,
+    device: String,
+    size: Option<u64>,
+}
+
+/// The result of parsing the command line.
+enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        check: false,
+        force: false,
+        quiet: false,
+        json: false,
+        page_size: None,
+        label: None,
+        version: SwapVersion::V1,
+        uuid: None,
+        lock: None,
+        device: String::new(),
+        size: None,
+    };
+    let mut positional = Vec::new();
+    let mut pending = None;
+
+    while let Some(arg) = pending.take().or_else(|| args.next()) {
+        let (flag, attached) = split_flag(&arg);
+        let mut value = |name: &str| {
+            attached
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("option '{}' requires an argument", name))
+        };
+
+        match flag.as_str() {
+            "-c" | "--check" => {
+                no_value(&flag, attached.as_deref(), &mut pending)?;
+                options.check = true;
+            }
+            "-f" | "--force" => {
+                no_value(&flag, attached.as_deref(), &mut pending)?;
+                options.force = true;
+            }
+            "-q" | "--quiet" => {
+                no_value(&flag, attached.as_deref(), &mut pending)?;
+                options.quiet = true;
+            }
+            "--json" => {
+                no_value(&flag, attached.as_deref(), &mut pending)?;
+                options.json = true;
+            }
+            "-p" | "--pagesize" => {
+                let page_size = value(&flag)?;
+                options.page_size = Some(
+                    page_size
+                        .parse()
+                        .map_err(|_| format!("invalid page size: '{}'", page_size))?,
+                );
+            }
+            "-L" | "--label" => options.label = Some(value(&flag)?),
+            "-v" | "--swapversion" => {
+                options.version = match value(&flag)?.as_str() {
+                    "0" => SwapVersion::V0,
+                    "1" => SwapVersion::V1,
+                    version => {
+                        return Err(format!("swapspace version {} is not supported", version))
+                    }
+                }
+            }
+            "-U" | "--uuid" => {
+                let uuid = value(&flag)?;
+                options.uuid = Some(
+                    uuid.parse()
+                        .map_err(|_| format!("error: parsing UUID failed: '{}'", uuid))?,
+                );
+            }
+            "--lock" => {
+                options.lock = match attached.as_deref() {
+                    None | Some("yes") | Some("1") => Some(LockMode::Wait),
+                    Some("no") | Some("0") => None,
+                    Some("nonblock") => Some(LockMode::NoWait),
+                    Some(mode) => return Err(format!("unsupported lock mode: {}", mode)),
+                }
+            }
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unrecognized option '{}'", arg))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.device = positional.next().ok_or("no device specified")?;
+    if let Some(size) = positional.next() {
+        // Like util-linux, a bare number is a count of 1 KiB blocks.
+        let size = match size.parse::<u64>() {
+            Ok(blocks) => blocks.checked_mul(1024),
+            Err(_) => parse_size(&size).ok(),
+        }
+        .ok_or_else(|| format!("invalid block count argument: '{}'", size))?;
+        options.size = Some(size);
+    }
+    if positional.next().is_some() {
+        return Err("too many arguments".into());
+    }
+
+    Ok(Command::Run(options))
+}
+
+fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = open_device(&options.device).map_err(|e| match e {
+        mkswap::Error::OpenDevice(e) => format!("cannot open {}: {}", options.device, e),
+        e => format!("cannot open {}: {}", options.device, e),
+    })?;
+
+    let mut warnings = Vec::new();
+    let device_size = device_size(&file)?;
+    let page_size = options.page_size.unwrap_or_else(|| page_size::get() as u64);
+    // Like util-linux, zero the bootbits: a partition table there is refused
+    // without --force. A swap area too large for the header is truncated
+    // with a warning.
+    let mut writer = SwapWriter::new()
+        .bootbits(BootBits::Zero)
+        .zero_first_page(true)
+        .clamp(ClampPolicy::ClampWithWarning)
+        .force(options.force)
+        .version(options.version)
+        .page_size(page_size);
+    // Character devices have no size, so leave those to be detected by seeking.
+    let size = options.size.or((device_size != 0).then_some(device_size));
+    if let Some(size) = size {
+        if device_size != 0 && size > device_size {
+            let message = format!(
+                "size {} KiB is larger than device size {} KiB",
+                size / 1024,
+                device_size / 1024
+            );
+            if !options.force {
+                return Err(format!("error: {}", message).into());
+            }
+            warnings.push(message);
+        }
+        writer = writer.size(size);
+    }
+    if let (SwapVersion::V1, Some(mut uuid)) = (options.version, options.uuid) {
+        writer = writer.uuid_source(&mut uuid);
+    }
+    // Like util-linux, a long label is truncated with a warning. Version 0
+    // swap spaces have nowhere to store one.
+    match (options.version, &options.label) {
+        (SwapVersion::V1, Some(label)) => writer = writer.label_lossy(label),
+        (SwapVersion::V0, Some(_)) => {
+            warnings.push("version 0 swap spaces have no label, ignoring it".to_string())
+        }
+        (_, None) => {}
+    }
+
+    let mut bad_pages = 0;
+    if options.check {
+        let (checked, found) = writer.check_file(&file)?;
+        writer = checked;
+        bad_pages = found;
+        if !options.quiet && !options.json {
+            match bad_pages {
+                0 => println!("0 bad pages"),
+                1 => println!("1 bad page"),
+                n => println!("{} bad pages", n),
+            }
+        }
+    }
+
+    // Like util-linux, old signatures are always wiped. Unlike it, signatures
+    // other than swap are refused without --force. Holes read back as zeros,
+    // so filling them doesn't change the contents.
+    if let Some(lock) = options.lock {
+        writer = writer.lock(lock);
+    }
+    let summary = writer
+        .fill_holes(true)
+        .wipe(true)
+        .write_file(&mut file)
+        .map_err(|e| match e {
+            mkswap::Error::SignatureFound(signature) => format!(
+                "{}: found an existing {} signature, use -f to overwrite it",
+                options.device,
+                signature.name()
+            )
+            .into(),
+            mkswap::Error::PartitionTableFound(table) => format!(
+                "{}: error: don't erase bootbits sectors ({} partition table detected). Use -f to force.",
+                options.device,
+                table.name()
+            )
+            .into(),
+            mkswap::Error::HasPartitions(partitions) => format!(
+                "{}: error: device has partitions ({}). Use -f to force.",
+                options.device,
+                partitions.join(", ")
+            )
+            .into(),
+            mkswap::Error::DeviceLocked => {
+                format!("{}: device already locked", options.device).into()
+            }
+            mkswap::Error::TooFewPages(_, minimum) => {
+                format!("error: swap area needs to be at least {} KiB", minimum / 1024).into()
+            }
+            e => Box::<dyn std::error::Error>::from(e),
+        })?;
+    warnings.extend(summary.warnings.iter().map(ToString::to_string));
+    // Like util-linux, report the size without the header page.
+    let size = summary.usable_bytes - page_size;
+
+    if options.json {
+        // Read the header back, so pipelines know it holds what's reported.
+        let verified = SwapReader::new()
+            .page_size(page_size)
+            .read(&mut file)
+            .is_ok_and(|header| {
+                header.last_page == summary.pages - 1
+                    && (!header.uuid.is_nil()).then_some(header.uuid) == summary.uuid
+                    && (!header.label.is_empty()).then_some(&header.label) == summary.label.as_ref()
+            });
+        let report = serde_json::json!({
+            "device": options.device,
+            "version": match options.version {
+                SwapVersion::V0 => 0,
+                SwapVersion::V1 => 1,
+            },
+            "uuid": summary.uuid.map(|uuid| uuid.to_string()),
+            "label": summary.label,
+            "pages": summary.pages,
+            "page_size": page_size,
+            "size": size,
+            "checked": options.check,
+            "bad_pages": bad_pages,
+            "verified": verified,
+            "warnings": warnings,
+        });
+        println!("{}", report);
+    } else if !options.quiet {
+        for warning in &warnings {
+            eprintln!("mkswap: {}: warning: {}", options.device, warning);
+        }
+
+        println!(
+            "Setting up swapspace version {}, size = {} KiB ({} bytes)",
+            match options.version {
+                SwapVersion::V0 => 0,
+                SwapVersion::V1 => 1,
+            },
+            size / 1024,
+            size
+        );
+        if let Some(uuid) = summary.uuid {
+            match summary.label {
+                Some(label) => print!("LABEL={}, ", label),
+                None => print!("no label, "),
+            }
+            println!("UUID={}", uuid);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!(
+                "mkswap from {} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            );
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("mkswap: {}", e);
+            eprintln!("Try 'mkswap --help' for more information.");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("mkswap: {}", with_sources(&*e));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The error's message, followed by the messages of its sources.
+fn with_sources(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,189 @@
+//! A pure-Rust `swaplabel`, compatible with the util-linux flags.
+
+use std::fs::OpenOptions;
+use std::process::ExitCode;
+
+use mkswap::{SwapEditor, SwapReader, Warning};
+use uuid::Uuid;
+
+mod common;
+use common::{no_value, split_flag};
+
+const USAGE: &str = "\
+Usage:
+ swaplabel [options] <device>
+
+Display or change the label or UUID of a swap area.
+
+Options:
+ -L, --label <label> specify a new label
+ -U, --uuid <uuid>   specify a new uuid
+     --json          print the current values as JSON
+
+ -h, --help          display this help
+ -V, --version       display version
+";
+
+struct Options {
+    label: Option<String>,
+    uuid: Option<Uuid>,
+    json: bool,
+    device: String,
+}
+
+/// The result of parsing the command line.
+enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        label: None,
+        uuid: None,
+        json: false,
+        device: String::new(),
+    };
+    let mut positional = Vec::new();
+    let mut pending = None;
+
+    while let Some(arg) = pending.take().or_else(|| args.next()) {
+        let (flag, attached) = split_flag(&arg);
+        let mut value = |name: &str| {
+            attached
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("option '{}' requires an argument", name))
+        };
+
+        match flag.as_str() {
+            "-L" | "--label" => options.label = Some(value(&flag)?),
+            "-U" | "--uuid" => {
+                let uuid = value(&flag)?;
+                options.uuid = Some(
+                    Uuid::parse_str(&uuid)
+                        .map_err(|_| format!("failed to parse UUID: {}", uuid))?,
+                );
+            }
+            "--json" => {
+                no_value(&flag, attached.as_deref(), &mut pending)?;
+                options.json = true;
+            }
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unrecognized option '{}'", arg))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.device = positional.next().ok_or("no device specified")?;
+    if positional.next().is_some() {
+        return Err("too many arguments".into());
+    }
+
+    Ok(Command::Run(options))
+}
+
+fn run(options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let editing = options.label.is_some() || options.uuid.is_some();
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(editing)
+        .open(&options.device)
+        .map_err(|e| format!("cannot open {}: {}", options.device, e))?;
+
+    if editing {
+        let mut editor = SwapEditor::new();
+        if let Some(label) = options.label {
+            editor = editor.label(label)?;
+        }
+        if let Some(uuid) = options.uuid {
+            editor = editor.uuid(uuid);
+        }
+        editor.write(&mut file)?;
+        file.sync_all()?;
+        if !options.json {
+            return Ok(());
+        }
+    }
+
+    let header = SwapReader::new().read(&mut file)?;
+    let mut warnings = Vec::new();
+    let host = page_size::get() as u64;
+    if header.page_size != host {
+        warnings.push(Warning::PageSizeMismatch {
+            page_size: header.page_size,
+            host,
+        });
+    }
+    if options.json {
+        let report = serde_json::json!({
+            "device": options.device,
+            "uuid": (!header.uuid.is_nil()).then(|| header.uuid.to_string()),
+            "label": (!header.label.is_empty()).then_some(&header.label),
+            "pages": u64::from(header.last_page) + 1,
+            "page_size": header.page_size,
+            "bad_pages": header.nr_badpages,
+            "warnings": warnings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        });
+        println!("{}", report);
+    } else {
+        for warning in &warnings {
+            eprintln!("swaplabel: {}: warning: {}", options.device, warning);
+        }
+        if !header.label.is_empty() {
+            println!("LABEL: {}", header.label);
+        }
+        if !header.uuid.is_nil() {
+            println!("UUID:  {}", header.uuid);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!(
+                "swaplabel from {} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            );
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("swaplabel: {}", e);
+            eprintln!("Try 'swaplabel --help' for more information.");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("swaplabel: {}", with_sources(&*e));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The error's message, followed by the messages of its sources.
+fn with_sources(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,160 @@
+//! Report swap spaces as blkid's tags, for scripts written against blkid.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Error, ReadSeek, SwapReader, SwapVersion};
+
+/// A tag blkid reports for swap spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlkidTag {
+    /// `TYPE`: always `swap`.
+    Type,
+    /// `UUID`: the hyphenated UUID, unless it's nil.
+    Uuid,
+    /// `LABEL`: the label, unless it's empty.
+    Label,
+    /// `PAGESIZE`: the page size in bytes.
+    PageSize,
+    /// `VERSION`: `0` or `1`.
+    Version,
+}
+
+impl BlkidTag {
+    /// The tag's name, like `UUID`.
+    pub fn name(self) -> &'static str {
+        match self {
+            BlkidTag::Type => "TYPE",
+            BlkidTag::Uuid => "UUID",
+            BlkidTag::Label => "LABEL",
+            BlkidTag::PageSize => "PAGESIZE",
+            BlkidTag::Version => "VERSION",
+        }
+    }
+}
+
+/// The tags of a swap space, found by [`blkid_tags`].
+///
+/// The [`Display`](fmt::Display) form is blkid's default output, like
+/// `TYPE="swap" UUID="…" LABEL="…" PAGESIZE="4096" VERSION="1"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlkidTags(pub BTreeMap<BlkidTag, String>);
+
+impl BlkidTags {
+    /// The value of a tag, if the swap space has it.
+    pub fn get(&self, tag: BlkidTag) -> Option<&str> {
+        self.0.get(&tag).map(String::as_str)
+    }
+
+    /// The tags in the form of `blkid -o export`: a `KEY=value` line for
+    /// each, with characters the shell treats specially escaped, so the
+    /// output can be `eval`ed.
+    pub fn export(&self) -> String {
+        let mut export = String::new();
+        for (tag, value) in &self.0 {
+            export.push_str(tag.name());
+            export.push('=');
+            for c in value.chars() {
+                if !(c.is_ascii_alphanumeric() || "+,-./:=@_".contains(c)) {
+                    export.push('\\');
+                }
+                export.push(c);
+            }
+            export.push('\n');
+        }
+        export
+    }
+}
+
+impl fmt::Display for BlkidTags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (tag, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}=\"", tag.name())?;
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    write!(f, "\\")?;
+                }
+                write!(f, "{}", c)?;
+            }
+            write!(f, "\"")?;
+        }
+        Ok(())
+    }
+}
+
+/// Probe the handle for a swap space, and report it with the tags blkid
+/// would, or None if there's no swap space.
+///
+/// The page size is inferred as [`SwapReader::read`] infers it.
+pub fn blkid_tags<T: ReadSeek>(handle: T) -> Result<Option<BlkidTags>, Error> {
+    let header = match SwapReader::new().read(handle) {
+        Ok(header) => header,
+        Err(Error::MissingSignature) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut tags = BTreeMap::new();
+    tags.insert(BlkidTag::Type, String::from("swap"));
+    if !header.uuid.is_nil() {
+        tags.insert(BlkidTag::Uuid, header.uuid.hyphenated().to_string());
+    }
+    if !header.label.is_empty() {
+        tags.insert(BlkidTag::Label, header.label);
+    }
+    tags.insert(BlkidTag::PageSize, header.page_size.to_string());
+    let version = match header.version {
+        SwapVersion::V0 => "0",
+        SwapVersion::V1 => "1",
+    };
+    tags.insert(BlkidTag::Version, String::from(version));
+
+    Ok(Some(BlkidTags(tags)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::Cursor;
+    use uuid::Uuid;
+
+    #[test]
+    fn tags() {
+        assert_eq!(blkid_tags(Cursor::new(vec![0; 40 * 1024])).unwrap(), None);
+
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut buffer = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .uuid(uuid)
+            .label("my \"swap\"".into())
+            .unwrap()
+            .write(&mut buffer)
+            .unwrap();
+        let tags = blkid_tags(&mut buffer).unwrap().unwrap();
+        assert_eq!(tags.get(BlkidTag::Label), Some("my \"swap\""));
+        assert_eq!(
+            tags.to_string(),
+            r#"TYPE="swap" UUID="87705c6e-9673-4283-b33a-b87dbf6ec490" LABEL="my \"swap\"" PAGESIZE="4096" VERSION="1""#
+        );
+        assert_eq!(
+            tags.export(),
+            "TYPE=swap\nUUID=87705c6e-9673-4283-b33a-b87dbf6ec490\nLABEL=my\\ \\\"swap\\\"\nPAGESIZE=4096\nVERSION=1\n"
+        );
+
+        let mut buffer = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .version(SwapVersion::V0)
+            .write(&mut buffer)
+            .unwrap();
+        let tags = blkid_tags(&mut buffer).unwrap().unwrap();
+        assert_eq!(
+            tags.to_string(),
+            r#"TYPE="swap" PAGESIZE="4096" VERSION="0""#
+        );
+    }
+}
@@ -0,0 +1,574 @@
+//! Render the configuration which enables a swap space at boot.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use crate::{Error, WriteSummary};
+
+/// The highest priority a swap space can be given. Higher priorities are
+/// refused with [`Error::InvalidPriority`].
+pub const MAXIMUM_PRIORITY: u16 = 32767;
+
+/// Check the priority is at most MAXIMUM_PRIORITY.
+pub(crate) fn check_priority(priority: u16) -> Result<u16, Error> {
+    if priority > MAXIMUM_PRIORITY {
+        return Err(Error::InvalidPriority(priority));
+    }
+
+    Ok(priority)
+}
+
+/// When the kernel should discard (TRIM) the swap space's pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discard {
+    /// Discard the whole area once at activation, and pages as they are freed.
+    All,
+    /// Only discard the whole area once, at activation.
+    Once,
+    /// Only discard pages as they are freed.
+    Pages,
+}
+
+impl Discard {
+    /// The mount option swapon(8) reads this from.
+    fn option(self) -> &'static str {
+        match self {
+            Discard::All => "discard",
+            Discard::Once => "discard=once",
+            Discard::Pages => "discard=pages",
+        }
+    }
+}
+
+/// A swap line of `/etc/fstab`, rendered by its Display impl.
+///
+/// ```
+/// # use std::path::Path;
+/// let entry = mkswap::FstabEntry::for_file(Path::new("/var/swap file"))
+///     .priority(10)
+///     .unwrap();
+/// assert_eq!(entry.to_string(), "/var/swap\\040file none swap defaults,pri=10 0 0");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEntry {
+    spec: String,
+    priority: Option<u16>,
+    discard: Option<Discard>,
+}
+
+impl FstabEntry {
+    /// An entry for a swap partition or other block device, which was written
+    /// to `device`.
+    ///
+    /// The swap space is named by its UUID, which survives the device being
+    /// renamed, or by `device` for a V0 swap space, which has none.
+    pub fn for_device(summary: &WriteSummary, device: &Path) -> Self {
+        let spec = match summary.uuid {
+            Some(uuid) => format!("UUID={}", uuid),
+            None => escape(&device.to_string_lossy()),
+        };
+
+        Self {
+            spec,
+            priority: None,
+            discard: None,
+        }
+    }
+
+    /// An entry for a swap file, which is always named by its path: only
+    /// block devices can be found by UUID.
+    pub fn for_file(path: &Path) -> Self {
+        Self {
+            spec: escape(&path.to_string_lossy()),
+            priority: None,
+            discard: None,
+        }
+    }
+
+    /// Set the priority, up to [`MAXIMUM_PRIORITY`]: higher priority swap
+    /// spaces are used first.
+    pub fn priority(mut self, priority: u16) -> Result<Self, Error> {
+        self.priority = Some(check_priority(priority)?);
+        Ok(self)
+    }
+
+    /// Enable discarding freed pages, for SSDs and thinly provisioned storage
+    pub fn discard(mut self, discard: Discard) -> Self {
+        self.discard = Some(discard);
+        self
+    }
+
+    /// The comma separated mount options, starting with `defaults`.
+    fn options(&self) -> String {
+        let mut options = String::from("defaults");
+        if let Some(priority) = self.priority {
+            options.push_str(&format!(",pri={}", priority));
+        }
+        if let Some(discard) = self.discard {
+            options.push(',');
+            options.push_str(discard.option());
+        }
+        options
+    }
+}
+
+impl fmt::Display for FstabEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} none swap {} 0 0", self.spec, self.options())
+    }
+}
+
+/// A systemd `.swap` unit, rendered by its Display impl.
+///
+/// The unit must be installed under the name from [`SwapUnit::name`], which
+/// systemd derives from the path of the swap space.
+///
+/// ```
+/// # use std::path::Path;
+/// let unit = mkswap::SwapUnit::for_file(Path::new("/var/swap-file"));
+/// assert_eq!(unit.name(), "var-swap\\x2dfile.swap");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapUnit {
+    what: PathBuf,
+    priority: Option<u16>,
+    discard: Option<Discard>,
+}
+
+impl SwapUnit {
+    /// A unit for a swap partition or other block device, which was written
+    /// to `device`.
+    ///
+    /// The swap space is named by its `/dev/disk/by-uuid` link, which udev
+    /// creates, or by `device` for a V0 swap space, which has no UUID.
+    pub fn for_device(summary: &WriteSummary, device: &Path) -> Self {
+        let what = match summary.uuid {
+            Some(uuid) => PathBuf::from(format!("/dev/disk/by-uuid/{}", uuid)),
+            None => device.into(),
+        };
+
+        Self {
+            what,
+            priority: None,
+            discard: None,
+        }
+    }
+
+    /// A unit for a swap file, which is always named by its path.
+    pub fn for_file(path: &Path) -> Self {
+        Self {
+            what: path.into(),
+            priority: None,
+            discard: None,
+        }
+    }
+
+    /// Set the priority, up to [`MAXIMUM_PRIORITY`]: higher priority swap
+    /// spaces are used first.
+    pub fn priority(mut self, priority: u16) -> Result<Self, Error> {
+        self.priority = Some(check_priority(priority)?);
+        Ok(self)
+    }
+
+    /// Enable discarding freed pages, for SSDs and thinly provisioned storage
+    pub fn discard(mut self, discard: Discard) -> Self {
+        self.discard = Some(discard);
+        self
+    }
+
+    /// The unit's file name, which is its path escaped as by
+    /// `systemd-escape --path --suffix=swap`.
+    pub fn name(&self) -> String {
+        let path = self.what.to_string_lossy();
+        let parts: Vec<&str> = path
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .collect();
+        if parts.is_empty() {
+            return String::from("-.swap");
+        }
+
+        let mut name = String::new();
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                name.push('-');
+            }
+            for (j, &b) in part.as_bytes().iter().enumerate() {
+                let leading_dot = i == 0 && j == 0 && b == b'.';
+                if (b.is_ascii_alphanumeric() || b":_.".contains(&b)) && !leading_dot {
+                    name.push(b as char);
+                } else {
+                    name.push_str(&format!("\\x{:02x}", b));
+                }
+            }
+        }
+        name.push_str(".swap");
+        name
+    }
+}
+
+impl fmt::Display for SwapUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // % starts a specifier in unit files.
+        let what = self.what.to_string_lossy().replace('%', "%%");
+        writeln!(f, "[Unit]")?;
+        writeln!(f, "Description=Swap on {}", what)?;
+        writeln!(f)?;
+        writeln!(f, "[Swap]")?;
+        writeln!(f, "What={}", what)?;
+        if let Some(priority) = self.priority {
+            writeln!(f, "Priority={}", priority)?;
+        }
+        if let Some(discard) = self.discard {
+            writeln!(f, "Options={}", discard.option())?;
+        }
+        writeln!(f)?;
+        writeln!(f, "[Install]")?;
+        writeln!(f, "WantedBy=swap.target")
+    }
+}
+
+/// The raw device under an encrypted swap space, as named in
+/// `/etc/crypttab`.
+///
+/// The swap space's own UUID can't be used: a mapping with a throwaway key
+/// overwrites the raw device with ciphertext, destroying the header which
+/// holds it, and so the device would never be found again after the first
+/// boot. Neither can a kernel name like `/dev/sda2`, which may name another
+/// disk after a reboot, and have it overwritten in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptDevice {
+    /// The UUID of a GPT partition, which is stored in the partition table
+    /// rather than on the partition.
+    PartUuid(Uuid),
+    /// A stable path like `/dev/disk/by-id/…` or `/dev/disk/by-path/…`.
+    Path(PathBuf),
+}
+
+/// A throwaway-key encrypted swap space: a line of `/etc/crypttab`, which
+/// sets up a mapping keyed from `/dev/urandom` and formats it as swap on
+/// every boot, and the fstab line which enables it.
+///
+/// ```
+/// # use std::path::PathBuf;
+/// use mkswap::{CryptDevice, CrypttabEntry};
+///
+/// let device = CryptDevice::Path(PathBuf::from("/dev/disk/by-id/ata-disk-part2"));
+/// let entry = CrypttabEntry::new("cryptswap", device).unwrap();
+/// assert_eq!(
+///     entry.to_string(),
+///     "cryptswap /dev/disk/by-id/ata-disk-part2 /dev/urandom swap,cipher=aes-xts-plain64,size=512"
+/// );
+/// assert_eq!(
+///     entry.fstab().to_string(),
+///     "/dev/mapper/cryptswap none swap defaults 0 0"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrypttabEntry {
+    name: String,
+    device: CryptDevice,
+    cipher: String,
+    key_size: u32,
+}
+
+impl CrypttabEntry {
+    /// An entry mapping `device` to `/dev/mapper/<name>`, with a 512 bit
+    /// `aes-xts-plain64` key.
+    ///
+    /// The name must be non-empty, and neither it nor the device path may
+    /// contain whitespace, which would split the line's fields. The name may
+    /// not contain a slash either.
+    pub fn new(name: &str, device: CryptDevice) -> Result<Self, Error> {
+        if name.is_empty() || name.contains(|c: char| c == '/' || c.is_whitespace()) {
+            return Err(Error::InvalidCrypttabField(name.to_string()));
+        }
+        if let CryptDevice::Path(path) = &device {
+            let path = path.to_string_lossy();
+            if path.is_empty() || path.contains(char::is_whitespace) {
+                return Err(Error::InvalidCrypttabField(path.into_owned()));
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            device,
+            cipher: String::from("aes-xts-plain64"),
+            key_size: 512,
+        })
+    }
+
+    /// Set the cipher, as understood by cryptsetup(8).
+    pub fn cipher(mut self, cipher: &str) -> Result<Self, Error> {
+        if cipher.is_empty() || cipher.contains(|c: char| c == ',' || c.is_whitespace()) {
+            return Err(Error::InvalidCrypttabField(cipher.to_string()));
+        }
+
+        self.cipher = cipher.to_string();
+        Ok(self)
+    }
+
+    /// Set the size of the key in bits: for XTS, twice the AES key size.
+    pub fn key_size(mut self, bits: u32) -> Self {
+        self.key_size = bits;
+        self
+    }
+
+    /// The fstab line which enables the mapped swap space.
+    pub fn fstab(&self) -> FstabEntry {
+        FstabEntry::for_file(&self.mapper_path())
+    }
+
+    /// The path the mapping appears at.
+    pub fn mapper_path(&self) -> PathBuf {
+        Path::new("/dev/mapper").join(&self.name)
+    }
+}
+
+impl fmt::Display for CrypttabEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.name)?;
+        match &self.device {
+            CryptDevice::PartUuid(uuid) => write!(f, "PARTUUID={}", uuid)?,
+            CryptDevice::Path(path) => write!(f, "{}", path.display())?,
+        }
+        write!(
+            f,
+            " /dev/urandom swap,cipher={},size={}",
+            self.cipher, self.key_size
+        )
+    }
+}
+
+/// A plan for striping swap across several devices, like RAID0.
+///
+/// The kernel spreads pages round-robin across the active swap spaces with
+/// the highest priority, so each is given the same priority. Without one,
+/// each swap space gets a lower priority than the last, and is only used
+/// once the ones before it are full.
+///
+/// ```
+/// # use std::path::Path;
+/// let plan = mkswap::StripePlan::new(10)
+///     .unwrap()
+///     .file(Path::new("/mnt/a/swap"))
+///     .file(Path::new("/mnt/b/swap"));
+/// for entry in plan.fstab() {
+///     assert!(entry.to_string().contains("pri=10"));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StripePlan {
+    priority: u16,
+    discard: Option<Discard>,
+    members: Vec<(FstabEntry, SwapUnit)>,
+}
+
+impl StripePlan {
+    /// Construct a new StripePlan giving every swap space `priority`, which
+    /// is at most [`MAXIMUM_PRIORITY`].
+    pub fn new(priority: u16) -> Result<Self, Error> {
+        Ok(Self {
+            priority: check_priority(priority)?,
+            discard: None,
+            members: Vec::new(),
+        })
+    }
+
+    /// Add a swap partition or other block device, which was written to
+    /// `device`. See [`FstabEntry::for_device`].
+    pub fn device(mut self, summary: &WriteSummary, device: &Path) -> Self {
+        self.members.push((
+            FstabEntry::for_device(summary, device),
+            SwapUnit::for_device(summary, device),
+        ));
+        self
+    }
+
+    /// Add a swap file.
+    pub fn file(mut self, path: &Path) -> Self {
+        self.members
+            .push((FstabEntry::for_file(path), SwapUnit::for_file(path)));
+        self
+    }
+
+    /// Discard freed pages on every swap space.
+    pub fn discard(mut self, discard: Discard) -> Self {
+        self.discard = Some(discard);
+        self
+    }
+
+    /// The fstab lines for each swap space, in the order they were added.
+    pub fn fstab(&self) -> Vec<FstabEntry> {
+        self.members
+            .iter()
+            .map(|(entry, _)| FstabEntry {
+                priority: Some(self.priority),
+                discard: self.discard,
+                ..entry.clone()
+            })
+            .collect()
+    }
+
+    /// The systemd units for each swap space, in the order they were added.
+    pub fn units(&self) -> Vec<SwapUnit> {
+        self.members
+            .iter()
+            .map(|(_, unit)| SwapUnit {
+                priority: Some(self.priority),
+                discard: self.discard,
+                ..unit.clone()
+            })
+            .collect()
+    }
+}
+
+/// Escape the characters which would split an fstab field, as octal like
+/// getmntent(3) expects.
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A swap space with a fixed UUID, as written to a device.
+    fn summary() -> WriteSummary {
+        WriteSummary {
+            uuid: Some(Uuid::from_u128(0x0657fd6d_a4ab_43c4_84e5_0933c84b4f4f)),
+            label: None,
+            page_size: 4096,
+            pages: 10,
+            usable_bytes: 40960,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fstab() {
+        let mut summary = summary();
+        assert_eq!(
+            FstabEntry::for_device(&summary, Path::new("/dev/sda2"))
+                .priority(5)
+                .unwrap()
+                .discard(Discard::Once)
+                .to_string(),
+            "UUID=0657fd6d-a4ab-43c4-84e5-0933c84b4f4f none swap defaults,pri=5,discard=once 0 0"
+        );
+
+        summary.uuid = None;
+        assert_eq!(
+            FstabEntry::for_device(&summary, Path::new("/dev/disk/by-id/a\tb\\"))
+                .discard(Discard::All)
+                .to_string(),
+            "/dev/disk/by-id/a\\011b\\134 none swap defaults,discard 0 0"
+        );
+        assert!(matches!(
+            FstabEntry::for_file(Path::new("/swap")).priority(32768),
+            Err(Error::InvalidPriority(32768))
+        ));
+    }
+
+    #[test]
+    fn swap_unit() {
+        let summary = summary();
+        let unit = SwapUnit::for_device(&summary, Path::new("/dev/sda2"))
+            .priority(5)
+            .unwrap()
+            .discard(Discard::Pages);
+        assert_eq!(
+            unit.name(),
+            "dev-disk-by\\x2duuid-0657fd6d\\x2da4ab\\x2d43c4\\x2d84e5\\x2d0933c84b4f4f.swap"
+        );
+        assert_eq!(
+            unit.to_string(),
+            "[Unit]\n\
+             Description=Swap on /dev/disk/by-uuid/0657fd6d-a4ab-43c4-84e5-0933c84b4f4f\n\
+             \n\
+             [Swap]\n\
+             What=/dev/disk/by-uuid/0657fd6d-a4ab-43c4-84e5-0933c84b4f4f\n\
+             Priority=5\n\
+             Options=discard=pages\n\
+             \n\
+             [Install]\n\
+             WantedBy=swap.target\n"
+        );
+
+        let unit = SwapUnit::for_file(Path::new("//.swap/my file%/"));
+        assert_eq!(unit.name(), "\\x2eswap-my\\x20file\\x25.swap");
+        assert!(unit.to_string().contains("What=//.swap/my file%%/\n"));
+        assert_eq!(SwapUnit::for_file(Path::new("/")).name(), "-.swap");
+    }
+
+    #[test]
+    fn stripe() {
+        let summary = summary();
+        let plan = StripePlan::new(100)
+            .unwrap()
+            .device(&summary, Path::new("/dev/nvme0n1p3"))
+            .file(Path::new("/data/swap"))
+            .discard(Discard::All);
+        assert_eq!(
+            plan.fstab()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "UUID=0657fd6d-a4ab-43c4-84e5-0933c84b4f4f none swap defaults,pri=100,discard 0 0",
+                "/data/swap none swap defaults,pri=100,discard 0 0",
+            ]
+        );
+        let units = plan.units();
+        assert_eq!(units[1].name(), "data-swap.swap");
+        assert!(units
+            .iter()
+            .all(|unit| unit.to_string().contains("Priority=100\nOptions=discard\n")));
+        assert!(matches!(
+            StripePlan::new(32768),
+            Err(Error::InvalidPriority(32768))
+        ));
+    }
+
+    #[test]
+    fn crypttab() {
+        let device = CryptDevice::PartUuid(Uuid::from_u128(0x0657fd6d_a4ab_43c4_84e5_0933c84b4f4f));
+        let entry = CrypttabEntry::new("swap0", device.clone())
+            .unwrap()
+            .cipher("aes-cbc-essiv:sha256")
+            .unwrap()
+            .key_size(256);
+        assert_eq!(
+            entry.to_string(),
+            "swap0 PARTUUID=0657fd6d-a4ab-43c4-84e5-0933c84b4f4f /dev/urandom swap,cipher=aes-cbc-essiv:sha256,size=256"
+        );
+        assert_eq!(entry.mapper_path(), Path::new("/dev/mapper/swap0"));
+
+        for name in ["", "a/b", "a b"] {
+            assert!(matches!(
+                CrypttabEntry::new(name, device.clone()),
+                Err(Error::InvalidCrypttabField(_))
+            ));
+        }
+        assert!(matches!(
+            CrypttabEntry::new(
+                "swap0",
+                CryptDevice::Path(PathBuf::from("/dev/disk/by-id/a b"))
+            ),
+            Err(Error::InvalidCrypttabField(_))
+        ));
+        assert!(matches!(
+            entry.cipher("aes,xts"),
+            Err(Error::InvalidCrypttabField(_))
+        ));
+    }
+}
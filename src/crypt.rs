@@ -0,0 +1,134 @@
+//! Set up throwaway-key encrypted swap with a plain dm-crypt mapping, through
+//! libcryptsetup.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use libcryptsetup_rs::consts::flags::{CryptActivate, CryptDeactivate};
+use libcryptsetup_rs::consts::vals::EncryptionFormat;
+use libcryptsetup_rs::{CryptInit, CryptParamsPlainRef, Either};
+
+use crate::{open_device, swapon, Error, SwapOnOptions, SwapWriter, WriteSummary};
+
+/// A builder for an encrypted swap space: a plain dm-crypt mapping over a
+/// device, keyed with random data which is never stored, and formatted as
+/// swap.
+///
+/// Everything swapped out is lost once the mapping is removed, so it must
+/// be set up again on every boot, as the `swap` option of crypttab does.
+#[derive(Debug, Clone)]
+pub struct CryptSwap {
+    name: String,
+    cipher: String,
+    cipher_mode: String,
+    key_size: usize,
+    activate: Option<SwapOnOptions>,
+}
+
+/// What [`CryptSwap::setup`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CryptSummary {
+    /// The path of the mapping, which the swap space was written to.
+    pub mapper_path: PathBuf,
+    /// What was written to the mapping.
+    pub swap: WriteSummary,
+    /// Whether the swap space was activated.
+    pub activated: bool,
+}
+
+impl CryptSwap {
+    /// Construct a new CryptSwap, mapping the device to `/dev/mapper/<name>`
+    /// with a 512 bit `aes-xts-plain64` key.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            cipher: String::from("aes"),
+            cipher_mode: String::from("xts-plain64"),
+            key_size: 512,
+            activate: None,
+        }
+    }
+
+    /// Set the cipher and its mode, like `aes` and `xts-plain64`.
+    pub fn cipher(mut self, cipher: &str, mode: &str) -> Self {
+        self.cipher = cipher.to_string();
+        self.cipher_mode = mode.to_string();
+        self
+    }
+
+    /// Set the size of the key in bits: for XTS, twice the AES key size.
+    pub fn key_size(mut self, bits: usize) -> Self {
+        self.key_size = bits;
+        self
+    }
+
+    /// Activate the swap space with these options once it's written.
+    pub fn activate(mut self, options: SwapOnOptions) -> Self {
+        self.activate = Some(options);
+        self
+    }
+
+    /// Map the device, and write the swap space to the mapping with
+    /// `writer`.
+    ///
+    /// Anything on the device is destroyed. If writing or activating the
+    /// swap space fails, the mapping is removed again. This needs root.
+    pub fn setup<P: AsRef<Path>>(
+        self,
+        device: P,
+        writer: SwapWriter,
+    ) -> Result<CryptSummary, Error> {
+        let mut key = vec![0; self.key_size / 8];
+        File::open("/dev/urandom")
+            .and_then(|mut random| random.read_exact(&mut key))
+            .map_err(Error::RandomKey)?;
+
+        let mut crypt = CryptInit::init(device.as_ref()).map_err(Error::Crypt)?;
+        let formatted = crypt.context_handle().format::<CryptParamsPlainRef<'_>>(
+            EncryptionFormat::Plain,
+            (self.cipher.as_str(), self.cipher_mode.as_str()),
+            None,
+            Either::Left(&key[..]),
+            None,
+        );
+        let mapped = formatted.and_then(|()| {
+            crypt.activate_handle().activate_by_volume_key(
+                Some(self.name.as_str()),
+                Some(&key[..]),
+                CryptActivate::empty(),
+            )
+        });
+        key.fill(0);
+        mapped.map_err(Error::Crypt)?;
+
+        let mapper_path = Path::new("/dev/mapper").join(&self.name);
+        let formatted = self.format(&mapper_path, writer);
+        if formatted.is_err() {
+            // The mapping holds nothing yet, and its key is gone.
+            let _ = crypt
+                .activate_handle()
+                .deactivate(&self.name, CryptDeactivate::empty());
+        }
+        let swap = formatted?;
+
+        Ok(CryptSummary {
+            mapper_path,
+            swap,
+            activated: self.activate.is_some(),
+        })
+    }
+
+    /// Write the swap space to the mapping, and activate it if asked.
+    fn format(&self, mapper_path: &Path, writer: SwapWriter) -> Result<WriteSummary, Error> {
+        let mut mapping = open_device(mapper_path)?;
+        let summary = writer.write(&mut mapping)?;
+        mapping.sync_all().map_err(Error::WriteHeader)?;
+        drop(mapping);
+
+        if let Some(options) = &self.activate {
+            swapon(mapper_path, options)?;
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,211 @@
+//! Query and manage Linux block devices.
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+const BLKGETSIZE64: libc::Ioctl = libc::_IOR::<u64>(0x12, 114);
+const BLKDISCARD: libc::Ioctl = libc::_IO(0x12, 119);
+const BLKZEROOUT: libc::Ioctl = libc::_IO(0x12, 127);
+
+/// The unit BLKDISCARD and BLKZEROOUT ranges must be aligned to.
+const SECTOR_BYTES: u64 = 512;
+
+/// Open a swap file or device for reading and writing.
+///
+/// Block devices are opened with O_EXCL, so this fails with
+/// [`Error::DeviceBusy`] if the device is mounted, or is held by another
+/// user like a device-mapper target or software RAID.
+pub fn open_device<P: AsRef<Path>>(path: P) -> Result<File, Error> {
+    let path = path.as_ref();
+    let is_block_device = std::fs::metadata(path)
+        .map_err(Error::OpenDevice)?
+        .file_type()
+        .is_block_device();
+
+    let mut options = OpenOptions::new();
+    options.read(true).write(true);
+    if is_block_device {
+        options.custom_flags(libc::O_EXCL);
+    }
+
+    options.open(path).map_err(|e| match e.raw_os_error() {
+        Some(libc::EBUSY) => Error::DeviceBusy,
+        _ => Error::OpenDevice(e),
+    })
+}
+
+/// How to wait for an exclusive lock on a swap file or device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Wait as long as it takes for the lock.
+    Wait,
+    /// Fail immediately with [`Error::DeviceLocked`] if the lock is held.
+    NoWait,
+    /// Fail with [`Error::DeviceLocked`] if the lock isn't acquired in time.
+    Timeout(Duration),
+}
+
+/// How often to retry the lock while waiting for a timeout.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Take an exclusive BSD lock on the file, with flock(2).
+pub(crate) fn lock(file: &File, mode: LockMode) -> Result<(), Error> {
+    let flock = |operation| {
+        // SAFETY: flock only operates on the file descriptor.
+        match unsafe { libc::flock(file.as_raw_fd(), operation) } {
+            0 => Ok(true),
+            _ => match std::io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+                e => Err(Error::Lock(e)),
+            },
+        }
+    };
+
+    let locked = match mode {
+        LockMode::Wait => flock(libc::LOCK_EX)?,
+        LockMode::NoWait => flock(libc::LOCK_EX | libc::LOCK_NB)?,
+        LockMode::Timeout(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if flock(libc::LOCK_EX | libc::LOCK_NB)? {
+                    break true;
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break false;
+                }
+                std::thread::sleep(LOCK_RETRY_INTERVAL.min(deadline - now));
+            }
+        }
+    };
+
+    if locked {
+        Ok(())
+    } else {
+        Err(Error::DeviceLocked)
+    }
+}
+
+/// Release a lock taken with [`lock`].
+pub(crate) fn unlock(file: &File) {
+    // SAFETY: flock only operates on the file descriptor. Closing the file
+    // releases the lock anyway, so there is nothing to do if this fails.
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) };
+}
+
+/// Detect the size in bytes of a swap file or device.
+///
+/// Block devices are queried with the BLKGETSIZE64 ioctl, as seeking to
+/// their end is unreliable or slow for some drivers. The size of anything
+/// else is taken from its metadata.
+pub fn device_size(file: &File) -> Result<u64, Error> {
+    let metadata = file.metadata().map_err(Error::SizeDetection)?;
+    if !metadata.file_type().is_block_device() {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(size = metadata.len(), "read the size of the file");
+        #[cfg(feature = "log")]
+        log::debug!("read a size of {} bytes for the file", metadata.len());
+        return Ok(metadata.len());
+    }
+
+    let mut size: u64 = 0;
+    // SAFETY: BLKGETSIZE64 writes a u64 through the pointer, which is valid
+    // for the duration of the call.
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64, &mut size) } != 0 {
+        return Err(Error::SizeDetection(std::io::Error::last_os_error()));
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(size, "read the size of the block device");
+    #[cfg(feature = "log")]
+    log::debug!("read a size of {} bytes for the block device", size);
+
+    Ok(size)
+}
+
+/// Zero the start of a block device with the BLKZEROOUT ioctl, which lets
+/// the device zero it without transferring any data, or unmap it.
+///
+/// Only whole sectors are zeroed. Returns how many bytes were zeroed: none
+/// if the target isn't a block device, or the device doesn't support it.
+///
+/// The data can't be recovered afterwards, so this is only called once the
+/// swap space to be written is known to be valid.
+pub(crate) fn zero_out(file: &File, len: u64) -> Result<u64, Error> {
+    let metadata = file.metadata().map_err(Error::SizeDetection)?;
+    let len = len - len % SECTOR_BYTES;
+    if !metadata.file_type().is_block_device() || len == 0 {
+        return Ok(0);
+    }
+
+    let range: [u64; 2] = [0, len];
+    // SAFETY: BLKZEROOUT reads a start and length through the pointer, which
+    // is valid for the duration of the call.
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKZEROOUT, &range) } != 0 {
+        let e = std::io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => Ok(0),
+            _ => Err(Error::Erase(e)),
+        };
+    }
+
+    Ok(len)
+}
+
+/// Discard the start of a block device with the BLKDISCARD ioctl, telling
+/// an SSD or thinly provisioned device that the data isn't needed.
+///
+/// Only whole sectors are discarded. Returns whether the device was
+/// discarded: it isn't if the target isn't a block device, or the device
+/// doesn't support it.
+///
+/// Like [`zero_out`], this is only called once the swap space to be written
+/// is known to be valid, as the data is lost.
+pub(crate) fn discard(file: &File, len: u64) -> Result<bool, Error> {
+    let metadata = file.metadata().map_err(Error::SizeDetection)?;
+    let len = len - len % SECTOR_BYTES;
+    if !metadata.file_type().is_block_device() || len == 0 {
+        return Ok(false);
+    }
+
+    let range: [u64; 2] = [0, len];
+    // SAFETY: BLKDISCARD reads a start and length through the pointer, which
+    // is valid for the duration of the call.
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKDISCARD, &range) } != 0 {
+        let e = std::io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EOPNOTSUPP | libc::ENOTTY | libc::EINVAL) => Ok(false),
+            _ => Err(Error::Discard(e)),
+        };
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lock_modes() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let other = File::open(file.path()).unwrap();
+
+        lock(file.as_file(), LockMode::NoWait).unwrap();
+        assert!(matches!(
+            lock(&other, LockMode::NoWait),
+            Err(Error::DeviceLocked)
+        ));
+        assert!(matches!(
+            lock(&other, LockMode::Timeout(Duration::from_millis(50))),
+            Err(Error::DeviceLocked)
+        ));
+
+        unlock(file.as_file());
+        lock(&other, LockMode::Wait).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+//! Modify the header of an existing swap space.
+
+use std::io::{IoSlice, SeekFrom, Write};
+
+use uuid::Uuid;
+
+use crate::probe::find_magic;
+use crate::{
+    check_page_size, detect_size_bytes, trim_label, Error, ReadSeek, SwapReader, SwapVersion,
+    SwapWriter, SyncAll, WriteSummary, BOOTBITS_BYTES, MAXIMUM_LABEL_BYTES, SWAPSPACE2_MAGIC,
+};
+
+/// A builder to change the label or UUID of an existing swap space in
+/// place, like `swaplabel`.
+///
+/// Only the specified fields are written: the rest of the header,
+/// including the bad pages list, is left untouched.
+pub struct SwapEditor {
+    page_size: Option<u64>,
+    uuid: Option<Uuid>,
+    label: Option<Vec<u8>>,
+}
+
+impl SwapEditor {
+    /// Construct a new SwapEditor with all-default Nones
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            page_size: None,
+            uuid: None,
+            label: None,
+        }
+    }
+
+    /// Specify the page size the swap space was created with
+    pub fn page_size(mut self, page_size: u64) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Set a new label.
+    ///
+    /// Must be fewer than MAXIMUM_LABEL_BYTES, or an error is returned.
+    pub fn label(self, label: String) -> Result<Self, Error> {
+        self.label_bytes(label.as_bytes())
+    }
+
+    /// Set a new label as raw bytes, which needn't be UTF-8, as
+    /// [`SwapWriter::label_bytes`] does.
+    pub fn label_bytes(mut self, label: &[u8]) -> Result<Self, Error> {
+        let label = trim_label(label)?;
+        if label.len() > MAXIMUM_LABEL_BYTES {
+            return Err(Error::LabelTooLong);
+        }
+
+        self.label = Some(label.to_vec());
+        Ok(self)
+    }
+
+    /// Set a new UUID
+    pub fn uuid(mut self, uuid: Uuid) -> Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    /// Validate the existing swap space and write the new fields to it.
+    ///
+    /// If no page size was specified, it is inferred like in [`SwapReader::read`].
+    pub fn write<T: ReadSeek + Write>(self, mut handle: T) -> Result<(), Error> {
+        let mut reader = SwapReader::new();
+        if let Some(page_size) = self.page_size {
+            reader = reader.page_size(page_size);
+        }
+        if reader.read(&mut handle)?.version != SwapVersion::V1 {
+            return Err(Error::IdentityUnsupported);
+        }
+
+        let volume = self.label.map(|label| {
+            let mut volume = [0; MAXIMUM_LABEL_BYTES];
+            volume[..label.len()].copy_from_slice(&label);
+            volume
+        });
+        // The UUID is directly followed by the label, so both are written
+        // with one vectored call.
+        let mut fields = Vec::new();
+        let mut offset = BOOTBITS_BYTES + 28;
+        if let Some(uuid) = &self.uuid {
+            fields.push(IoSlice::new(uuid.as_bytes())); // sws_uuid
+            offset = BOOTBITS_BYTES + 12;
+        }
+        if let Some(volume) = &volume {
+            fields.push(IoSlice::new(volume)); // sws_volume
+        }
+        if !fields.is_empty() {
+            handle
+                .seek(SeekFrom::Start(offset))
+                .map_err(Error::WriteHeader)?;
+            write_all_vectored(&mut handle, &mut fields).map_err(Error::WriteHeader)?;
+        }
+        handle
+            .seek(SeekFrom::Start(0))
+            .map_err(Error::WriteHeader)?;
+
+        Ok(())
+    }
+}
+
+/// Write every buffer, retrying partial vectored writes until all are out.
+fn write_all_vectored<T: Write>(mut handle: T, mut bufs: &mut [IoSlice]) -> std::io::Result<()> {
+    while !bufs.is_empty() {
+        match handle.write_vectored(bufs) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(written) => IoSlice::advance_slices(&mut bufs, written),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite a swap space header for a different page size, preserving its
+/// UUID and label, like `swapon --fixpgsz`.
+///
+/// The existing page size is detected by probing, and the swap space keeps
+/// its size in bytes. The bad pages list is not preserved, as its page
+/// numbers are relative to the old page size.
+///
+/// Returns a summary of the rewritten swap space.
+pub fn fix_page_size<T: ReadSeek + SyncAll>(
+    mut handle: T,
+    page_size: u64,
+) -> Result<WriteSummary, Error> {
+    check_page_size(page_size)?;
+    let (_, old_page_size) = find_magic(&mut handle, |magic| {
+        (magic == SWAPSPACE2_MAGIC).then_some(())
+    })?
+    .ok_or(Error::MissingSignature)?;
+    let header = SwapReader::new()
+        .page_size(old_page_size)
+        .read(&mut handle)?;
+
+    let mut writer = SwapWriter::new()
+        .uuid(header.uuid)
+        .page_size(page_size)
+        .size((u64::from(header.last_page) + 1) * old_page_size);
+    if !header.raw_label().is_empty() {
+        writer = writer.label_bytes(header.raw_label())?;
+    }
+    // Check the new header can be built before touching the old one, so a
+    // failure leaves the swap space as it was.
+    writer.prepare(page_size, detect_size_bytes(&mut handle))?;
+
+    // Clear the old magic, so it can't be found before the new one.
+    handle
+        .seek(SeekFrom::Start(old_page_size - 10))
+        .map_err(Error::WriteHeader)?;
+    handle.write_all(&[0; 10]).map_err(Error::WriteHeader)?; // magic
+
+    writer.write(handle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Read, Seek};
+
+    #[test]
+    fn fix_page_size_preserves_identity() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 160 * 1024]);
+        SwapWriter::new()
+            .label("swap".into())
+            .unwrap()
+            .uuid(uuid)
+            .page_size(16384)
+            .write(&mut buffer)
+            .unwrap();
+
+        assert_eq!(
+            fix_page_size(&mut buffer, 4096).unwrap().usable_bytes,
+            160 * 1024
+        );
+        assert_eq!(&buffer.get_ref()[16374..16384], &[0; 10]);
+
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(header.last_page, 39);
+        assert_eq!(header.uuid, uuid);
+        assert_eq!(header.label, "swap");
+
+        // A label which isn't UTF-8 is kept byte for byte.
+        let label = b"\xff\xfe\xfd\xfc\xfb\xfa\xf9\xf8\xf7\xf6\xf5\xf4";
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 160 * 1024]);
+        SwapWriter::new()
+            .label_bytes(label)
+            .unwrap()
+            .page_size(16384)
+            .write(&mut buffer)
+            .unwrap();
+        fix_page_size(&mut buffer, 4096).unwrap();
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(header.raw_label(), label);
+
+        // A swap space too small for the new page size is left as it was.
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .uuid(uuid)
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        let before = buffer.get_ref().clone();
+        assert!(matches!(
+            fix_page_size(&mut buffer, 65536),
+            Err(Error::TooFewPages(0, _))
+        ));
+        assert_eq!(buffer.get_ref(), &before);
+        let header = SwapReader::new().read(&mut buffer).unwrap();
+        assert_eq!(header.uuid, uuid);
+    }
+    #[test]
+    fn edit_label_and_uuid() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .label("a long swap name".into())
+            .unwrap()
+            .page_size(4096)
+            .bad_pages(vec![5])
+            .write(&mut buffer)
+            .unwrap();
+
+        SwapEditor::new()
+            .page_size(4096)
+            .label("short".into())
+            .unwrap()
+            .uuid(uuid)
+            .write(&mut buffer)
+            .unwrap();
+
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(header.uuid, uuid);
+        assert_eq!(header.label, "short");
+        assert_eq!(header.nr_badpages, 1);
+        assert_eq!(&buffer.get_ref()[1536..1540], &5u32.to_ne_bytes());
+    }
+    #[test]
+    fn edit_short_writes() {
+        /// Writes at most 3 bytes of the first buffer per call.
+        struct ShortWrites(Cursor<Vec<u8>>);
+        impl Read for ShortWrites {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+        impl Write for ShortWrites {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(&buf[..buf.len().min(3)])
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl SyncAll for ShortWrites {}
+        impl Seek for ShortWrites {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut buffer = ShortWrites(Cursor::new(vec![0; 40 * 1024]));
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        SwapEditor::new()
+            .page_size(4096)
+            .label("vectored".into())
+            .unwrap()
+            .uuid(uuid)
+            .write(&mut buffer)
+            .unwrap();
+
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(header.uuid, uuid);
+        assert_eq!(header.label, "vectored");
+    }
+}
@@ -0,0 +1,61 @@
+//! Create and activate a swap space in one step.
+
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+use crate::{open_device, swapon, Error, SwapOnOptions, SwapWriter, WriteSummary};
+
+/// What [`SwapWriter::enable`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnableSummary {
+    /// The path of the swap file or device which was activated.
+    pub path: PathBuf,
+    /// Whether a new swap file was created, rather than formatting a device.
+    pub created_file: bool,
+    /// What was written to the swap file or device.
+    pub swap: WriteSummary,
+}
+
+impl SwapWriter {
+    /// Write the configured swap space and activate it.
+    ///
+    /// If the path is a block device, the swap space is written to it.
+    /// Otherwise a new swap file is created with [`SwapWriter::create_file`],
+    /// which needs a size to be specified and refuses to overwrite an
+    /// existing file.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(path = %path.as_ref().display()), err)
+    )]
+    pub fn enable<P: AsRef<Path>>(
+        self,
+        path: P,
+        options: &SwapOnOptions,
+    ) -> Result<EnableSummary, Error> {
+        let path = path.as_ref();
+
+        let is_block_device = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.file_type().is_block_device(),
+            Err(_) => false,
+        };
+        let swap = if is_block_device {
+            #[cfg(feature = "log")]
+            log::info!(
+                "writing a swap space to the block device {}",
+                path.display()
+            );
+            let mut device = open_device(path)?;
+            self.write_file(&mut device)?
+        } else {
+            self.create_file(path)?
+        };
+
+        swapon(path, options)?;
+
+        Ok(EnableSummary {
+            path: path.to_path_buf(),
+            created_file: !is_block_device,
+            swap,
+        })
+    }
+}
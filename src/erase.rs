@@ -0,0 +1,153 @@
+//! Erase the whole of a swap space before formatting it.
+
+use std::io::SeekFrom;
+
+use crate::{Error, Phase, Progress, WriteSeek};
+
+/// How many bytes are erased between progress reports.
+const ERASE_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// The data [`erase`] overwrites a swap space with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErasePattern {
+    /// Overwrite with zeros.
+    Zeros,
+    /// Overwrite with cryptographically secure random data.
+    #[cfg(feature = "rand")]
+    Random,
+}
+
+/// Overwrite the first `size` bytes of the handle, such as a whole device
+/// being decommissioned, before formatting it.
+///
+/// `progress` is reported to after each chunk, in the [`Phase::Erase`]
+/// phase, and then `should_continue` is checked: once it returns false,
+/// erasing stops with [`Error::Cancelled`], leaving the handle partly
+/// erased. The data is flushed, but not synced: for a
+/// [`File`](std::fs::File) call `sync_all` afterwards.
+pub fn erase<T: WriteSeek>(
+    handle: T,
+    size: u64,
+    pattern: ErasePattern,
+    mut progress: impl Progress,
+    should_continue: impl Fn() -> bool,
+) -> Result<(), Error> {
+    erase_from(handle, 0, size, pattern, |done, total| {
+        progress.report(Phase::Erase, done, total);
+        match should_continue() {
+            true => Ok(()),
+            false => Err(Error::Cancelled),
+        }
+    })
+}
+
+/// Erase the bytes from `start` up to `size`, for when the bytes before it
+/// were already zeroed some faster way.
+pub(crate) fn erase_from<T: WriteSeek>(
+    mut handle: T,
+    start: u64,
+    size: u64,
+    pattern: ErasePattern,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut fill = filler(pattern);
+    let buffer: &mut [u8] = &mut vec![0; size.min(ERASE_CHUNK_BYTES as u64) as usize];
+    handle.seek(SeekFrom::Start(start)).map_err(Error::Erase)?;
+    let mut done = start;
+    while done < size {
+        let chunk = (size - done).min(buffer.len() as u64) as usize;
+        fill(&mut buffer[..chunk]);
+        handle.write_all(&buffer[..chunk]).map_err(Error::Erase)?;
+        done += chunk as u64;
+        progress(done, size)?;
+    }
+    handle.flush().map_err(Error::Erase)?;
+    handle.seek(SeekFrom::Start(0)).map_err(Error::Erase)?;
+
+    Ok(())
+}
+
+/// Fill zeroed buffers with the pattern before each write. Zeros are left
+/// as they are.
+pub(crate) fn filler(pattern: ErasePattern) -> impl FnMut(&mut [u8]) {
+    #[cfg(feature = "rand")]
+    let mut rng = match pattern {
+        ErasePattern::Random => Some(<rand::rngs::StdRng as rand::SeedableRng>::from_entropy()),
+        ErasePattern::Zeros => None,
+    };
+    #[cfg(not(feature = "rand"))]
+    let _ = pattern;
+
+    move |_buffer: &mut [u8]| {
+        #[cfg(feature = "rand")]
+        if let Some(rng) = &mut rng {
+            rand::RngCore::fill_bytes(rng, _buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn erase_zeros() {
+        let mut buffer = Cursor::new(vec![0xff; 3 * ERASE_CHUNK_BYTES]);
+        let mut reports = Vec::new();
+        erase(
+            &mut buffer,
+            2 * ERASE_CHUNK_BYTES as u64 + 1,
+            ErasePattern::Zeros,
+            |phase, done, total| reports.push((phase, done, total)),
+            || true,
+        )
+        .unwrap();
+
+        let total = 2 * ERASE_CHUNK_BYTES as u64 + 1;
+        assert_eq!(
+            reports,
+            vec![
+                (Phase::Erase, ERASE_CHUNK_BYTES as u64, total),
+                (Phase::Erase, 2 * ERASE_CHUNK_BYTES as u64, total),
+                (Phase::Erase, total, total),
+            ]
+        );
+        let bytes = buffer.into_inner();
+        assert!(bytes[..total as usize].iter().all(|&b| b == 0));
+        assert!(bytes[total as usize..].iter().all(|&b| b == 0xff));
+    }
+
+    #[test]
+    fn erase_cancelled() {
+        let mut buffer = Cursor::new(vec![0xff; 3 * ERASE_CHUNK_BYTES]);
+        let mut chunks = 0;
+        let result = erase(
+            &mut buffer,
+            3 * ERASE_CHUNK_BYTES as u64,
+            ErasePattern::Zeros,
+            |_, _, _| chunks += 1,
+            || false,
+        );
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(chunks, 1);
+        let bytes = buffer.into_inner();
+        assert!(bytes[..ERASE_CHUNK_BYTES].iter().all(|&b| b == 0));
+        assert!(bytes[ERASE_CHUNK_BYTES..].iter().all(|&b| b == 0xff));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn erase_random() {
+        let mut buffer = Cursor::new(vec![0; 4096]);
+        erase(
+            &mut buffer,
+            4096,
+            ErasePattern::Random,
+            |_, _, _| {},
+            || true,
+        )
+        .unwrap();
+        assert!(buffer.get_ref().iter().any(|&b| b != 0));
+    }
+}
@@ -0,0 +1,185 @@
+//! A C API, for installers written in C and other languages' bindings.
+//!
+//! The header, `include/mkswap.h`, is generated from this module with
+//! `cbindgen --output include/mkswap.h`. Build the library for C with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or
+//! `staticlib`).
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs::File;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::FromRawFd;
+
+use crate::{probe, Error, SwapVersion, SwapWriter};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The options of `mkswap_write`. A zeroed struct writes a swap space with
+/// the defaults.
+#[repr(C)]
+pub struct MkswapOptions {
+    /// The page size in bytes, or 0 for the running system's.
+    pub page_size: u64,
+    /// The size of the swap space in bytes, or 0 for the whole file or
+    /// device.
+    pub size: u64,
+    /// The label, as a NUL-terminated UTF-8 string, or NULL for none.
+    pub label: *const c_char,
+    /// The UUID, or all zeros for a random one.
+    pub uuid: [u8; 16],
+    /// Skip the safety checks refusing to overwrite a swap space, mounted
+    /// device or other signature in use.
+    pub force: bool,
+}
+
+/// What `mkswap_probe` found.
+#[repr(C)]
+pub enum MkswapVersion {
+    /// No swap signature.
+    None = 0,
+    /// A legacy `SWAP-SPACE` swap space.
+    V0 = 1,
+    /// A current `SWAPSPACE2` swap space.
+    V1 = 2,
+}
+
+impl MkswapOptions {
+    /// # Safety
+    ///
+    /// The label must be NULL, or point to a NUL-terminated string.
+    unsafe fn writer(&self) -> Result<SwapWriter, Error> {
+        let mut writer = SwapWriter::new().force(self.force);
+        if self.page_size != 0 {
+            writer = writer.page_size(self.page_size);
+        }
+        if self.size != 0 {
+            writer = writer.size(self.size);
+        }
+        if self.uuid != [0; 16] {
+            writer = writer.uuid_bytes(self.uuid);
+        }
+        if !self.label.is_null() {
+            // SAFETY: the caller passes a NUL-terminated label.
+            let label = unsafe { CStr::from_ptr(self.label) };
+            let label = label.to_str().map_err(|_| Error::LabelNotUtf8)?;
+            writer = writer.label(label.to_string())?;
+        }
+
+        Ok(writer)
+    }
+}
+
+/// Record the error and its sources for `mkswap_last_error`, and return the
+/// failure code.
+fn fail(error: Error) -> c_int {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message = format!("{}: {}", message, cause);
+        source = cause.source();
+    }
+    // The messages never contain a NUL.
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    -1
+}
+
+/// Write a swap space to the open file or device `fd`, like
+/// `SwapWriter::write_file`. `options` may be NULL for the defaults.
+///
+/// Returns 0 on success, or -1 with the error in `mkswap_last_error`. The
+/// file descriptor is left open.
+///
+/// # Safety
+///
+/// `fd` must be open for reading and writing, and `options` must be NULL
+/// or point to a valid `MkswapOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn mkswap_write(fd: c_int, options: *const MkswapOptions) -> c_int {
+    // SAFETY: the caller passes a valid pointer or NULL.
+    let writer = match unsafe { options.as_ref() } {
+        // SAFETY: the caller passes a NUL-terminated label.
+        Some(options) => unsafe { options.writer() },
+        None => Ok(SwapWriter::new()),
+    };
+    // SAFETY: the caller passes an open file descriptor, which is borrowed
+    // and never closed.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+
+    match writer.and_then(|writer| writer.write_file(&mut file)) {
+        Ok(_) => 0,
+        Err(e) => fail(e),
+    }
+}
+
+/// Check whether the open file or device `fd` contains a swap signature,
+/// like `probe`.
+///
+/// Returns a `MkswapVersion`, or -1 with the error in `mkswap_last_error`.
+/// The file descriptor is left open.
+///
+/// # Safety
+///
+/// `fd` must be open for reading.
+#[no_mangle]
+pub unsafe extern "C" fn mkswap_probe(fd: c_int) -> c_int {
+    // SAFETY: the caller passes an open file descriptor, which is borrowed
+    // and never closed.
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+
+    let version = match probe(&*file) {
+        Ok(None) => MkswapVersion::None,
+        Ok(Some(SwapVersion::V0)) => MkswapVersion::V0,
+        Ok(Some(SwapVersion::V1)) => MkswapVersion::V1,
+        Err(e) => return fail(e),
+    };
+    version as c_int
+}
+
+/// The message of the last error on this thread, or NULL if there was none.
+///
+/// The string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn mkswap_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn write_and_probe() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0; 40 * 4096]).unwrap();
+        let fd = file.as_raw_fd();
+
+        assert_eq!(unsafe { mkswap_probe(fd) }, MkswapVersion::None as c_int);
+        let options = MkswapOptions {
+            page_size: 4096,
+            size: 0,
+            label: c"ffi".as_ptr(),
+            uuid: [7; 16],
+            force: false,
+        };
+        assert_eq!(unsafe { mkswap_write(fd, &options) }, 0);
+        assert_eq!(unsafe { mkswap_probe(fd) }, MkswapVersion::V1 as c_int);
+
+        let options = MkswapOptions {
+            label: c"a label which is far too long".as_ptr(),
+            ..options
+        };
+        assert_eq!(unsafe { mkswap_write(fd, &options) }, -1);
+        let message = unsafe { CStr::from_ptr(mkswap_last_error()) };
+        assert_eq!(message.to_str().unwrap(), Error::LabelTooLong.to_string());
+    }
+}
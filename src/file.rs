@@ -0,0 +1,638 @@
+//! Create swap files on Linux filesystems.
+
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::device::{self, device_size, LockMode};
+use crate::{
+    detect_partition_table, detect_signatures, erase, system, wipe_signatures, ErasePattern, Error,
+    FoundSignature, Phase, Signature, Size, SwapWriter, Warning, WriteSummary,
+};
+
+/// The inode flag disabling copy-on-write, from linux/fs.h.
+const FS_NOCOW_FL: libc::c_int = 0x0080_0000;
+
+/// How a new swap file's blocks are allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prealloc {
+    /// Allocate with fallocate(2). This is fast, but some filesystems
+    /// historically refused to activate swap files allocated this way.
+    Fallocate,
+    /// Write zeros over the whole file. This is slow, but works everywhere.
+    ZeroWrite,
+    /// Only set the file's length, leaving it sparse. The kernel refuses to
+    /// activate sparse swap files, so the caller must allocate it.
+    None,
+}
+
+impl Prealloc {
+    /// Pick the allocation strategy for the filesystem holding the file.
+    ///
+    /// Per swapon(8), fallocated swap files are supported on ext4 and btrfs,
+    /// and on XFS since Linux 4.18. Writing zeros is the portable choice for
+    /// everything else, including older XFS.
+    fn detect(file: &File) -> std::io::Result<Self> {
+        let filesystem = filesystem_type(file)?;
+        // The types of these vary between architectures.
+        #[allow(clippy::unnecessary_cast)]
+        let fallocate_supported = [
+            libc::EXT4_SUPER_MAGIC as i64,
+            libc::BTRFS_SUPER_MAGIC as i64,
+        ];
+
+        if fallocate_supported.contains(&filesystem) {
+            Ok(Prealloc::Fallocate)
+        } else {
+            Ok(Prealloc::ZeroWrite)
+        }
+    }
+}
+
+/// The options only used when writing to a [`File`].
+#[derive(Default)]
+pub(crate) struct FileOptions {
+    prealloc: Option<Prealloc>,
+    fill_holes: bool,
+    lock: Option<LockMode>,
+    wipe: bool,
+    backup: Option<PathBuf>,
+    erase: Option<ErasePattern>,
+    discard: bool,
+}
+
+/// Create a swap file of the given size in bytes.
+///
+/// This is a shorthand for [`SwapWriter::create_file`] with a default
+/// SwapWriter.
+pub fn create_swap_file<P: AsRef<Path>>(path: P, size: u64) -> Result<WriteSummary, Error> {
+    SwapWriter::new().size(size).create_file(path)
+}
+
+impl SwapWriter {
+    /// Create a new swap file, and write the configured swap space to it.
+    ///
+    /// The file is created with mode 0600, copy-on-write is disabled where
+    /// the filesystem supports it, and the whole size is allocated so the
+    /// file has no holes (see [`SwapWriter::prealloc`]). The kernel refuses
+    /// to activate swap files which don't meet all of these requirements.
+    ///
+    /// A size must be specified, and the file must not already exist.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(path = %path.as_ref().display()), err)
+    )]
+    pub fn create_file<P: AsRef<Path>>(self, path: P) -> Result<WriteSummary, Error> {
+        if self.offset != 0 {
+            return Err(Error::OffsetUnsupported);
+        }
+        let page_size = self.resolve_page_size()?;
+        let size = match self.size {
+            Some(Size::Bytes(size)) => size,
+            Some(Size::Pages(pages)) => u64::from(pages) * page_size,
+            None => return Err(Error::SizeRequired),
+        };
+        #[cfg(feature = "log")]
+        log::info!("creating the swap file {}", path.as_ref().display());
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(Error::CreateFile)?;
+
+        let result = self.fill_file(&mut file, size);
+        if result.is_err() {
+            // Don't leave a half-created swap file behind.
+            let _ = std::fs::remove_file(&path);
+        }
+        result
+    }
+
+    /// Specify how [`SwapWriter::create_file`] allocates the file's blocks
+    ///
+    /// If not specified, the strategy is picked based on the filesystem.
+    pub fn prealloc(mut self, prealloc: Prealloc) -> Self {
+        self.file.prealloc = Some(prealloc);
+        self
+    }
+
+    /// Fill holes in sparse swap files with zeros, instead of refusing them
+    ///
+    /// Only used by [`SwapWriter::write_file`]. Defaults to false.
+    pub fn fill_holes(mut self, fill_holes: bool) -> Self {
+        self.file.fill_holes = fill_holes;
+        self
+    }
+
+    /// Take an exclusive lock on the file or device while writing to it
+    ///
+    /// Only used by [`SwapWriter::write_file`]. This is the same BSD lock
+    /// udev takes while probing a device, so holding it avoids racing udev.
+    pub fn lock(mut self, lock: LockMode) -> Self {
+        self.file.lock = Some(lock);
+        self
+    }
+
+    /// Zero old signatures with [`wipe_signatures`] before writing
+    ///
+    /// Only used by [`SwapWriter::write_file`]. Defaults to false. When set,
+    /// an old swap space is wiped and replaced without needing
+    /// [`SwapWriter::force`], but other signatures still need it.
+    pub fn wipe(mut self, wipe: bool) -> Self {
+        self.file.wipe = wipe;
+        self
+    }
+
+    /// Save the regions which will be overwritten to a new backup file
+    /// before writing, with [`backup`](crate::backup)
+    ///
+    /// Only used by [`SwapWriter::write_file`]. The backup file must not
+    /// already exist.
+    pub fn backup<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.file.backup = Some(path.into());
+        self
+    }
+
+    /// Erase the whole swap space with [`erase`] before writing
+    ///
+    /// Only used by [`SwapWriter::write_file`], once the swap space is known
+    /// to fit, and after writing any [`SwapWriter::backup`]. Defaults to not
+    /// erasing. Block devices are
+    /// zeroed with the BLKZEROOUT ioctl where they support it, which is much
+    /// faster than writing zeros. With the `uring` feature, anything else is
+    /// written through io_uring where the kernel supports it.
+    pub fn erase(mut self, pattern: ErasePattern) -> Self {
+        self.file.erase = Some(pattern);
+        self
+    }
+
+    /// Discard the whole device with the BLKDISCARD ioctl before writing
+    ///
+    /// Only used by [`SwapWriter::write_file`], once the swap space is known
+    /// to fit, and after any [`SwapWriter::erase`]. Defaults to false. This is skipped for files,
+    /// and devices which don't support discarding, like spinning disks.
+    pub fn discard(mut self, discard: bool) -> Self {
+        self.file.discard = discard;
+        self
+    }
+
+    /// Write the configured swap space to an existing swap file or device.
+    ///
+    /// Regular files are first checked for holes, which the kernel refuses
+    /// in swap files. Holes are filled with zeros if
+    /// [`SwapWriter::fill_holes`] is set, and otherwise an error is returned.
+    ///
+    /// If no size was specified, it is detected with [`device_size`].
+    ///
+    /// Unless [`SwapWriter::force`] is set, this refuses to overwrite a swap
+    /// space which is listed as active in /proc/swaps, a mounted device, a
+    /// whole disk with partitions or a partition table (see
+    /// [`detect_partition_table`]), or a
+    /// recognized filesystem or swap signature (see [`detect_signatures`]).
+    ///
+    /// These checks and the other steps work on the whole file or device, so
+    /// [`SwapWriter::offset`] isn't supported.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, err)
+    )]
+    pub fn write_file(self, file: &mut File) -> Result<WriteSummary, Error> {
+        if self.offset != 0 {
+            return Err(Error::OffsetUnsupported);
+        }
+        let lock = self.file.lock;
+        if let Some(lock) = lock {
+            device::lock(file, lock)?;
+        }
+        let result = self.write_file_locked(file);
+        if lock.is_some() {
+            device::unlock(file);
+        }
+        result
+    }
+
+    /// Read every page of a swap file or device, marking unreadable pages as
+    /// bad, as [`SwapWriter::check`] does.
+    ///
+    /// With the `uring` feature, the pages are read in large batches through
+    /// io_uring where the kernel supports it, which is much faster on large
+    /// devices.
+    pub fn check_file(self, file: &File) -> Result<(Self, u32), Error> {
+        #[cfg(feature = "uring")]
+        {
+            let mut this = self;
+            let page_size = this.resolve_page_size()?;
+            let mut slice = this.slice(file, page_size, None)?;
+            let total_size_bytes = this.resolve_size_bytes(&mut slice, page_size)?;
+            let pages = this.count_pages(total_size_bytes, page_size)?;
+            let offset = this.offset;
+
+            let found = crate::uring::read_only(file, offset, page_size, pages, |done, total| {
+                this.report(Phase::Scan, done, total)
+            })?;
+            match found {
+                Some(found) => Ok(this.add_bad_pages(found)),
+                None => this.check(file),
+            }
+        }
+        #[cfg(not(feature = "uring"))]
+        self.check(file)
+    }
+
+    fn write_file_locked(mut self, file: &mut File) -> Result<WriteSummary, Error> {
+        if !self.force {
+            if system::is_active_swap(file)? {
+                return Err(Error::SwapActive);
+            }
+            if let Some(mount_point) = system::mount_point(file)? {
+                return Err(Error::Mounted(mount_point));
+            }
+            let partitions = system::partitions(file)?;
+            if !partitions.is_empty() {
+                return Err(Error::HasPartitions(partitions));
+            }
+            if let Some(table) = detect_partition_table(&mut *file)? {
+                return Err(Error::PartitionTableFound(table));
+            }
+            // Replacing an old swap space is the point of wiping it.
+            let wiped_swap =
+                |found: &FoundSignature| self.file.wipe && found.signature == Signature::Swap;
+            let found = detect_signatures(&mut *file)?;
+            if let Some(found) = found.iter().find(|found| !wiped_swap(found)) {
+                return Err(Error::SignatureFound(found.signature));
+            }
+        }
+
+        // Seeking to the end of a block device is unreliable or slow, so the
+        // size is only ever taken from device_size.
+        let end = device_size(file)?;
+        if self.size.is_none() {
+            self = self.size(end);
+        }
+        let page_size = self.resolve_page_size()?;
+        let size = self.resolve_size_bytes(&mut *file, page_size)?;
+        // Check the swap space fits and its header can be built before
+        // anything is overwritten, so a write which would fail leaves the
+        // file as it was.
+        self.prepare(page_size, Ok(end))?;
+        let mut warnings = Vec::new();
+
+        if let Some(path) = &self.file.backup {
+            let mut backup = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .map_err(Error::Backup)?;
+            crate::backup(&mut *file, page_size, &mut backup)?;
+            backup.sync_all().map_err(Error::Backup)?;
+        }
+
+        if let Some(pattern) = self.file.erase {
+            #[cfg(feature = "tracing")]
+            tracing::info!(?pattern, size, "erasing");
+            #[cfg(feature = "log")]
+            log::info!("erasing {} bytes with {:?}", size, pattern);
+            // The swap space was validated above, so zeroing can't be
+            // followed by a failure to build the header.
+            let zeroed = if pattern == ErasePattern::Zeros {
+                device::zero_out(file, size)?
+            } else {
+                0
+            };
+            self.report(Phase::Erase, zeroed, size)?;
+            #[cfg(feature = "uring")]
+            let erased = crate::uring::erase(file, zeroed, size, pattern, |done, total| {
+                self.report(Phase::Erase, done, total)
+            })?;
+            #[cfg(not(feature = "uring"))]
+            let erased = false;
+            if !erased {
+                erase::erase_from(&mut *file, zeroed, size, pattern, |done, total| {
+                    self.report(Phase::Erase, done, total)
+                })?;
+            }
+        }
+
+        if self.file.discard {
+            #[cfg(feature = "tracing")]
+            tracing::info!(size, "discarding");
+            #[cfg(feature = "log")]
+            log::info!("discarding {} bytes", size);
+            self.report(Phase::Discard, 0, size)?;
+            device::discard(file, size)?;
+            self.report(Phase::Discard, size, size)?;
+        }
+
+        let metadata = file.metadata().map_err(Error::SizeDetection)?;
+        if metadata.is_file() {
+            // Anyone who can read the file can read the swapped out memory.
+            let mode = metadata.permissions().mode() & 0o7777;
+            if mode & 0o077 != 0 {
+                warnings.push(Warning::InsecurePermissions(mode));
+            }
+            let holes = find_holes(file, size).map_err(Error::SizeDetection)?;
+            if let Some(hole) = holes.first() {
+                if !self.file.fill_holes {
+                    return Err(Error::SparseFile(hole.start));
+                }
+                zero_holes(file, &holes, |done, total| {
+                    self.report(Phase::FillHoles, done, total)
+                })?;
+                warnings.push(Warning::FilledHoles);
+            }
+        }
+
+        if self.file.wipe {
+            for found in wipe_signatures(&mut *file)? {
+                warnings.push(Warning::WipedSignature(found));
+            }
+        }
+
+        #[cfg(feature = "log")]
+        for warning in &warnings {
+            log::warn!("{}", warning);
+        }
+        let mut summary = self.write_sized(&mut *file, Some(end))?;
+        file.sync_all().map_err(Error::WriteHeader)?;
+        summary.warnings.extend(warnings);
+
+        Ok(summary)
+    }
+
+    fn fill_file(mut self, file: &mut File, size: u64) -> Result<WriteSummary, Error> {
+        // The mode passed to open is subject to the umask.
+        file.set_permissions(Permissions::from_mode(0o600))
+            .map_err(Error::CreateFile)?;
+
+        // Copy-on-write can only be disabled while the file is empty. btrfs
+        // refuses to activate copy-on-write swap files, so the flag is
+        // required there. Other filesystems may not support the flag at all.
+        #[allow(clippy::unnecessary_cast)]
+        if filesystem_type(file).map_err(Error::NoCow)? == libc::BTRFS_SUPER_MAGIC as i64 {
+            set_nocow(file).map_err(Error::NoCow)?;
+        } else {
+            let _ = set_nocow(file);
+        }
+        let prealloc = match self.file.prealloc {
+            Some(prealloc) => prealloc,
+            None => Prealloc::detect(file).map_err(Error::Allocate)?,
+        };
+        allocate(file, size, prealloc, |done, total| {
+            self.report(Phase::Allocate, done, total)
+        })?;
+
+        let summary = self.write(&mut *file)?;
+        file.sync_all().map_err(Error::WriteHeader)?;
+
+        Ok(summary)
+    }
+}
+
+/// Find the holes in the first `size` bytes of the file, using SEEK_HOLE and
+/// SEEK_DATA. Filesystems without support for them report no holes.
+fn find_holes(file: &File, size: u64) -> std::io::Result<Vec<Range<u64>>> {
+    let seek = |offset: u64, whence| -> std::io::Result<Option<u64>> {
+        // SAFETY: lseek only operates on the file descriptor.
+        match unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) } {
+            -1 => match std::io::Error::last_os_error() {
+                // There is no data or hole after the offset.
+                e if e.raw_os_error() == Some(libc::ENXIO) => Ok(None),
+                e => Err(e),
+            },
+            position => Ok(Some(position as u64)),
+        }
+    };
+
+    let mut holes = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        let hole = match seek(offset, libc::SEEK_HOLE) {
+            Ok(Some(hole)) if hole < size => hole,
+            Ok(_) => break,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => break,
+            Err(e) => return Err(e),
+        };
+        let data = seek(hole, libc::SEEK_DATA)?.unwrap_or(size).min(size);
+        holes.push(hole..data);
+        offset = data;
+    }
+
+    Ok(holes)
+}
+
+fn zero_holes(
+    file: &mut File,
+    holes: &[Range<u64>],
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let zeros = vec![0; 1024 * 1024];
+    let total = holes.iter().map(|hole| hole.end - hole.start).sum();
+    let mut done = 0;
+    for hole in holes {
+        file.seek(SeekFrom::Start(hole.start))
+            .map_err(Error::Allocate)?;
+        write_zeros(file, &zeros, hole.end - hole.start, |written| {
+            progress(done + written, total)
+        })?;
+        done += hole.end - hole.start;
+    }
+
+    Ok(())
+}
+
+fn write_zeros(
+    file: &mut File,
+    zeros: &[u8],
+    len: u64,
+    mut progress: impl FnMut(u64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk]).map_err(Error::Allocate)?;
+        remaining -= chunk as u64;
+        progress(len - remaining)?;
+    }
+
+    Ok(())
+}
+
+fn filesystem_type(file: &File) -> std::io::Result<i64> {
+    let mut statfs = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: fstatfs fills in the statfs struct when it succeeds.
+    let statfs = unsafe {
+        if libc::fstatfs(file.as_raw_fd(), statfs.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        statfs.assume_init()
+    };
+
+    // The type varies between architectures.
+    #[allow(clippy::unnecessary_cast)]
+    Ok(statfs.f_type as i64)
+}
+
+fn set_nocow(file: &File) -> std::io::Result<()> {
+    let mut flags: libc::c_int = 0;
+    // SAFETY: FS_IOC_GETFLAGS and FS_IOC_SETFLAGS read and write an int
+    // through the pointer, which is valid for the duration of the calls.
+    unsafe {
+        if libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        flags |= FS_NOCOW_FL;
+        if libc::ioctl(file.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Allocate the file with the given strategy. fallocate(2) falls back to
+/// writing zeros if the filesystem doesn't support it.
+fn allocate(
+    file: &mut File,
+    size: u64,
+    prealloc: Prealloc,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<(), Error> {
+    if prealloc == Prealloc::None {
+        return file.set_len(size).map_err(Error::Allocate);
+    }
+
+    if prealloc == Prealloc::Fallocate {
+        let len = libc::off_t::try_from(size)
+            .map_err(|_| Error::Allocate(std::io::ErrorKind::InvalidInput.into()))?;
+        // SAFETY: fallocate only operates on the file descriptor.
+        if unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len) } == 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EOPNOTSUPP) {
+            return Err(Error::Allocate(error));
+        }
+    }
+
+    write_zeros(file, &vec![0; 1024 * 1024], size, |written| {
+        progress(written, size)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapReader;
+    use std::os::unix::fs::FileExt;
+
+    #[test]
+    fn create_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("swapfile");
+
+        let written = SwapWriter::new()
+            .page_size(4096)
+            .prealloc(Prealloc::ZeroWrite)
+            .size_in_pages(16)
+            .unwrap()
+            .create_file(&path)
+            .unwrap();
+        assert_eq!(written.usable_bytes, 16 * 4096);
+        assert_eq!(written.warnings, vec![]);
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len(), 16 * 4096);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        let header = SwapReader::new()
+            .page_size(4096)
+            .read(File::open(&path).unwrap())
+            .unwrap();
+        assert_eq!(header.last_page, 15);
+        assert_eq!(
+            find_holes(&File::open(&path).unwrap(), 16 * 4096).unwrap(),
+            vec![]
+        );
+
+        std::fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let rewritten = SwapWriter::new()
+            .page_size(4096)
+            .force(true)
+            .write_file(&mut file)
+            .unwrap();
+        assert_eq!(
+            rewritten.warnings,
+            vec![Warning::InsecurePermissions(0o644)]
+        );
+        assert_eq!(
+            rewritten.warnings[0].to_string(),
+            "insecure permissions 0644, fix with: chmod 0600"
+        );
+
+        assert!(matches!(
+            create_swap_file(&path, 16 * 4096),
+            Err(Error::CreateFile(_))
+        ));
+    }
+    #[test]
+    fn sparse_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(16 * 4096).unwrap();
+        let mut file = file.into_file();
+        if find_holes(&file, 16 * 4096).unwrap().is_empty() {
+            // This filesystem doesn't report holes.
+            return;
+        }
+
+        assert!(matches!(
+            SwapWriter::new().page_size(4096).write_file(&mut file),
+            Err(Error::SparseFile(0))
+        ));
+
+        SwapWriter::new()
+            .page_size(4096)
+            .fill_holes(true)
+            .write_file(&mut file)
+            .unwrap();
+        assert_eq!(find_holes(&file, 16 * 4096).unwrap(), vec![]);
+    }
+    #[test]
+    fn failed_write_changes_nothing() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0xaa; 40 * 1024]).unwrap();
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .size(1 << 20)
+                .erase(ErasePattern::Zeros)
+                .write_file(&mut file),
+            Err(Error::SizeExceedsTarget(40960))
+        ));
+        assert_eq!(file.metadata().unwrap().len(), 40 * 1024);
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0xaa; 4096]).unwrap();
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .force(true)
+                .erase(ErasePattern::Zeros)
+                .write_file(&mut file),
+            Err(Error::TooFewPages(1, _))
+        ));
+        let mut contents = vec![0; 4096];
+        file.read_exact_at(&mut contents, 0).unwrap();
+        assert_eq!(contents, vec![0xaa; 4096]);
+    }
+}
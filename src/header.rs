@@ -0,0 +1,526 @@
+//! Build swap space headers in memory, without any I/O, so they can be
+//! built without std.
+
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+#[cfg(all(feature = "host-page-size", not(target_family = "wasm")))]
+use crate::runtime_page_size;
+use crate::{
+    max_badpages, max_swap_bytes, ArchLimitPolicy, Error, Size, SwapVersion, SwapWriter, Warning,
+    WriteSummary, BADPAGES_OFFSET, BOOTBITS_BYTES, MAXIMUM_LABEL_BYTES, SWAPSPACE2_MAGIC,
+    SWAP_SPACE_MAGIC,
+};
+#[cfg(feature = "std")]
+use crate::{BootBits, HEADER_END};
+
+/// A field of the first page of a swap space, as named in the kernel's
+/// `union swap_header`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderField {
+    /// The bootbits, the first 1024 bytes of a V1 swap space.
+    Bootbits,
+    /// The format version.
+    Version,
+    /// The number of the last page.
+    LastPage,
+    /// The number of bad pages.
+    NrBadpages,
+    /// The UUID.
+    Uuid,
+    /// The label.
+    Label,
+    /// The unused space between the label and the bad pages.
+    Padding,
+    /// The list of bad pages.
+    Badpages,
+    /// The usable pages bitmap of a V0 swap space.
+    Bitmap,
+    /// The magic at the end of the page.
+    Magic,
+}
+
+impl HeaderField {
+    #[cfg(feature = "std")]
+    /// The field holding the byte at `offset` in the first page.
+    pub(crate) fn at(version: SwapVersion, page_size: u64, offset: u64) -> Self {
+        match version {
+            _ if offset >= page_size - 10 => HeaderField::Magic,
+            SwapVersion::V0 => HeaderField::Bitmap,
+            SwapVersion::V1 => match offset.saturating_sub(BOOTBITS_BYTES) {
+                _ if offset < BOOTBITS_BYTES => HeaderField::Bootbits,
+                0..=3 => HeaderField::Version,
+                4..=7 => HeaderField::LastPage,
+                8..=11 => HeaderField::NrBadpages,
+                12..=27 => HeaderField::Uuid,
+                28..=43 => HeaderField::Label,
+                _ if offset < BADPAGES_OFFSET => HeaderField::Padding,
+                _ => HeaderField::Badpages,
+            },
+        }
+    }
+
+    /// The kernel's name for the field, like `last_page`.
+    pub fn name(self) -> &'static str {
+        match self {
+            HeaderField::Bootbits => "bootbits",
+            HeaderField::Version => "version",
+            HeaderField::LastPage => "last_page",
+            HeaderField::NrBadpages => "nr_badpages",
+            HeaderField::Uuid => "uuid",
+            HeaderField::Label => "label",
+            HeaderField::Padding => "padding",
+            HeaderField::Badpages => "badpages",
+            HeaderField::Bitmap => "bitmap",
+            HeaderField::Magic => "magic",
+        }
+    }
+}
+
+/// Write `page[start..end]` out like `write_all`, but report which field a
+/// failed write was in, and where. The page starts at `base` in the handle.
+#[cfg(feature = "std")]
+pub(crate) fn write_region<T: std::io::Write>(
+    mut out: T,
+    version: SwapVersion,
+    page: &[u8],
+    (start, end): (u64, u64),
+    base: u64,
+) -> Result<(), Error> {
+    let mut offset = start;
+    while offset < end {
+        match out.write(&page[offset as usize..end as usize]) {
+            Ok(0) => {
+                return Err(field_error(
+                    version,
+                    page,
+                    offset,
+                    base,
+                    std::io::ErrorKind::WriteZero.into(),
+                ))
+            }
+            Ok(written) => offset += written as u64,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(field_error(version, page, offset, base, e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// The error for a failed write at `offset` in the page.
+#[cfg(feature = "std")]
+pub(crate) fn field_error(
+    version: SwapVersion,
+    page: &[u8],
+    offset: u64,
+    base: u64,
+    source: std::io::Error,
+) -> Error {
+    Error::WriteField {
+        field: HeaderField::at(version, page.len() as u64, offset),
+        offset: base + offset,
+        source,
+    }
+}
+
+/// The validated size of a swap space, and the warnings found validating it.
+pub(crate) struct Layout {
+    pub(crate) page_size: u64,
+    pub(crate) pages: u32,
+    /// The size in bytes, clamped to what the pages describe.
+    pub(crate) total_size_bytes: u64,
+    pub(crate) warnings: Vec<Warning>,
+}
+
+/// The first page of a swap space, built in memory.
+#[cfg(feature = "std")]
+pub(crate) struct Header {
+    pub(crate) version: SwapVersion,
+    pub(crate) page: Vec<u8>,
+    pub(crate) uuid: Option<[u8; 16]>,
+    /// The `[start, end)` byte ranges of the page to write, leaving the rest
+    /// as it was.
+    pub(crate) regions: Vec<(u64, u64)>,
+}
+
+impl SwapWriter {
+    /// Build the first page of the swap space in memory, without any I/O.
+    ///
+    /// The size must be specified, or [`Error::SizeRequired`] is returned.
+    /// The page is built like [`write_stream`](SwapWriter::write_stream)
+    /// builds it, with zeroed bootbits: writing it to the start of an area
+    /// of that size makes the area a swap space. Without the `std` feature,
+    /// this is how swap spaces are built.
+    ///
+    /// Returns the page, and a summary of the swap space it describes.
+    pub fn build_header(self) -> Result<(Vec<u8>, WriteSummary), Error> {
+        let page_size = self.resolve_page_size()?;
+        let total_size_bytes = match self.size {
+            Some(Size::Bytes(size)) => size,
+            Some(Size::Pages(pages)) => u64::from(pages) * page_size,
+            None => return Err(Error::SizeRequired),
+        };
+        let layout = self.layout(page_size, total_size_bytes)?;
+        let (page, uuid) = match self.version {
+            SwapVersion::V0 => (self.build_v0(&layout)?, None),
+            SwapVersion::V1 => {
+                let (page, uuid) = self.build_v1(&layout)?;
+                (page, Some(uuid))
+            }
+        };
+
+        Ok((page, self.summary(uuid, layout)))
+    }
+
+    /// Build the first page of the swap space, and pick the regions of it
+    /// to write.
+    #[cfg(feature = "std")]
+    pub(crate) fn header(&self, layout: &Layout) -> Result<Header, Error> {
+        let header = match self.version {
+            SwapVersion::V0 => Header {
+                version: SwapVersion::V0,
+                regions: vec![(0, layout.page_size)],
+                page: self.build_v0(layout)?,
+                uuid: None,
+            },
+            SwapVersion::V1 => {
+                let page_size = layout.page_size;
+                let (page, uuid) = self.build_v1(layout)?;
+                // Write the whole page where it is being zeroed anyway, and
+                // otherwise only the header fields, bad pages and magic.
+                let start = match self.bootbits {
+                    BootBits::Zero => 0,
+                    BootBits::Preserve => BOOTBITS_BYTES,
+                };
+                let regions = if self.zero_first_page {
+                    vec![(start, page_size)]
+                } else {
+                    vec![
+                        (start, HEADER_END),
+                        (
+                            BADPAGES_OFFSET,
+                            BADPAGES_OFFSET + 4 * self.bad_pages.len() as u64,
+                        ),
+                        (page_size - 10, page_size),
+                    ]
+                };
+                Header {
+                    version: SwapVersion::V1,
+                    page,
+                    uuid: Some(uuid),
+                    regions: regions
+                        .into_iter()
+                        .filter(|(start, end)| start < end)
+                        .collect(),
+                }
+            }
+        };
+
+        Ok(header)
+    }
+
+    /// Resolve how many pages the swap space has, and check they can all be
+    /// described and used.
+    pub(crate) fn layout(&self, page_size: u64, total_size_bytes: u64) -> Result<Layout, Error> {
+        let pages = self.count_pages(total_size_bytes, page_size)?;
+        let mut warnings = Vec::new();
+        if let (label, Some(length)) = self.volume()? {
+            warnings.push(Warning::LabelTruncated {
+                length,
+                kept: label.len(),
+            });
+        }
+        // Without the host's page size, there's nothing to compare against.
+        #[cfg(all(feature = "host-page-size", not(target_family = "wasm")))]
+        match (self.page_size, runtime_page_size()?) {
+            (Some(_), host) if host != page_size => {
+                warnings.push(Warning::PageSizeMismatch { page_size, host })
+            }
+            _ => {}
+        }
+        let total_size_bytes = if total_size_bytes / page_size > u64::from(pages) {
+            warnings.push(Warning::Clamped {
+                pages,
+                fit: total_size_bytes / page_size,
+            });
+            u64::from(pages) * page_size
+        } else {
+            total_size_bytes
+        };
+        if let Some((arch, policy)) = self.arch_limit {
+            let max = max_swap_bytes(page_size, arch);
+            match policy {
+                _ if total_size_bytes <= max => {}
+                ArchLimitPolicy::Error => return Err(Error::ExceedsArchLimit(max)),
+                ArchLimitPolicy::Warn => warnings.push(Warning::ExceedsArchLimit {
+                    arch,
+                    max,
+                    size: total_size_bytes,
+                }),
+            }
+        }
+        let last_page = pages - 1;
+        if let Some(&page) = self
+            .bad_pages
+            .iter()
+            .find(|&&page| page == 0 || page > last_page)
+        {
+            return Err(Error::InvalidBadPage(page));
+        }
+
+        Ok(Layout {
+            page_size,
+            pages,
+            total_size_bytes,
+            warnings,
+        })
+    }
+
+    /// Build the first page of a V1 swap space, with zeroed bootbits.
+    fn build_v1(&self, layout: &Layout) -> Result<(Vec<u8>, [u8; 16]), Error> {
+        let page_size = layout.page_size;
+        let last_page = layout.pages - 1;
+        let (label, _) = self.volume()?;
+        #[cfg(feature = "uuid")]
+        let uuid = self.uuid.unwrap_or_else(|| *Uuid::new_v4().as_bytes());
+        #[cfg(not(feature = "uuid"))]
+        let uuid = self.uuid.ok_or(Error::UuidRequired)?;
+        if self.bad_pages.len() > max_badpages(page_size) as usize {
+            return Err(Error::TooManyBadPages(self.bad_pages.len()));
+        }
+        let nr_badpages = self.bad_pages.len() as u32;
+
+        let mut page = vec![0; page_size as usize];
+        let mut field = |offset: u64, bytes: &[u8]| {
+            page[offset as usize..offset as usize + bytes.len()].copy_from_slice(bytes);
+        };
+        field(BOOTBITS_BYTES, &self.endianness.u32_to_bytes(1)); // version
+        field(BOOTBITS_BYTES + 4, &self.endianness.u32_to_bytes(last_page)); // last page
+        field(
+            BOOTBITS_BYTES + 8,
+            &self.endianness.u32_to_bytes(nr_badpages),
+        ); // number of bad pages
+        field(BOOTBITS_BYTES + 12, &uuid); // sws_uuid
+        field(BOOTBITS_BYTES + 28, label); // sws_volume
+        for (i, page) in self.bad_pages.iter().enumerate() {
+            field(
+                BADPAGES_OFFSET + 4 * i as u64,
+                &self.endianness.u32_to_bytes(*page),
+            ); // badpages
+        }
+        field(page_size - 10, SWAPSPACE2_MAGIC); // magic
+
+        Ok((page, uuid))
+    }
+
+    /// Build the first page of a V0 swap space: the usable pages bitmap and
+    /// the magic.
+    fn build_v0(&self, layout: &Layout) -> Result<Vec<u8>, Error> {
+        if self.label.is_some() || self.uuid.is_some() {
+            return Err(Error::IdentityUnsupported);
+        }
+        let pages = layout.pages;
+        let bitmap_bytes = layout.page_size - 10;
+        if u64::from(pages) > bitmap_bytes * 8 {
+            return Err(Error::TooManyPages(pages));
+        }
+
+        // Each bit marks a usable page. Page 0 is the header, so it is never usable.
+        let mut bitmap = vec![0u8; bitmap_bytes as usize];
+        for page in 1..pages {
+            bitmap[page as usize / 8] |= 1 << (page % 8);
+        }
+        for page in &self.bad_pages {
+            bitmap[*page as usize / 8] &= !(1 << (page % 8));
+        }
+        bitmap.extend_from_slice(SWAP_SPACE_MAGIC); // magic
+
+        Ok(bitmap)
+    }
+
+    /// The label as it's written, truncated if it was set by label_lossy,
+    /// with its length if it was truncated. Empty if there's no label.
+    pub(crate) fn volume(&self) -> Result<(&[u8], Option<usize>), Error> {
+        let Some(label) = &self.label else {
+            return Ok((&[], None));
+        };
+        if label.contains(&0) {
+            return Err(Error::LabelContainsNul);
+        }
+        let max = match self.label_terminator {
+            true => MAXIMUM_LABEL_BYTES - 1,
+            false => MAXIMUM_LABEL_BYTES,
+        };
+        if label.len() <= max {
+            return Ok((label, None));
+        }
+        if !self.lossy_label {
+            return Err(Error::LabelTooLong);
+        }
+
+        // Back off to the start of a character.
+        let mut end = max;
+        while end > 0 && label[end] & 0xc0 == 0x80 {
+            end -= 1;
+        }
+        Ok((&label[..end], Some(label.len())))
+    }
+
+    /// The label as it's written, replacing bytes which aren't UTF-8.
+    pub(crate) fn written_label(&self) -> Option<String> {
+        self.label.as_ref()?;
+        let (label, _) = self.volume().ok()?;
+        Some(String::from_utf8_lossy(label).into_owned())
+    }
+
+    #[cfg_attr(not(feature = "uuid"), allow(unused_variables))]
+    pub(crate) fn summary(self, uuid: Option<[u8; 16]>, layout: Layout) -> WriteSummary {
+        WriteSummary {
+            #[cfg(feature = "uuid")]
+            uuid: uuid.map(Uuid::from_bytes),
+            label: self.written_label(),
+            page_size: layout.page_size,
+            pages: layout.pages,
+            usable_bytes: layout.total_size_bytes - self.bad_pages.len() as u64 * layout.page_size,
+            warnings: layout.warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_header() {
+        let (page, summary) = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 4096)
+            .label("core".into())
+            .unwrap()
+            .uuid_bytes([7; 16])
+            .build_header()
+            .unwrap();
+        assert_eq!(page.len(), 4096);
+        assert_eq!(summary.pages, 40);
+        assert!(page[..BOOTBITS_BYTES as usize].iter().all(|&b| b == 0));
+        assert_eq!(&page[1024 + 4..1024 + 8], &39u32.to_ne_bytes());
+        assert_eq!(&page[1024 + 12..1024 + 28], &[7; 16]);
+        assert_eq!(&page[1024 + 28..1024 + 32], b"core");
+        assert_eq!(&page[4096 - 10..], SWAPSPACE2_MAGIC);
+
+        assert!(matches!(
+            SwapWriter::new().page_size(4096).build_header(),
+            Err(Error::SizeRequired)
+        ));
+        let generated = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 4096)
+            .build_header();
+        #[cfg(feature = "uuid")]
+        assert_eq!(generated.unwrap().1.uuid.unwrap().get_version_num(), 4);
+        #[cfg(not(feature = "uuid"))]
+        assert!(matches!(generated, Err(Error::UuidRequired)));
+    }
+
+    #[test]
+    fn label_bytes() {
+        let (page, summary) = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 4096)
+            .uuid_bytes([7; 16])
+            .label_bytes(b"caf\xe9")
+            .unwrap()
+            .build_header()
+            .unwrap();
+        assert_eq!(&page[1024 + 28..1024 + 33], b"caf\xe9\0");
+        assert_eq!(summary.label.as_deref(), Some("caf\u{fffd}"));
+
+        assert!(matches!(
+            SwapWriter::new().label_bytes(&[b'a'; MAXIMUM_LABEL_BYTES + 1]),
+            Err(Error::LabelTooLong)
+        ));
+    }
+
+    #[test]
+    fn label_nuls() {
+        let build = |writer: SwapWriter| {
+            writer
+                .page_size(4096)
+                .size(40 * 4096)
+                .uuid_bytes([7; 16])
+                .build_header()
+        };
+
+        let (_, summary) = build(SwapWriter::new().label_bytes(b"padded\0\0\0").unwrap()).unwrap();
+        assert_eq!(summary.label.as_deref(), Some("padded"));
+        assert!(matches!(
+            SwapWriter::new().label_bytes(b"in\0side"),
+            Err(Error::LabelContainsNul)
+        ));
+        assert!(matches!(
+            build(SwapWriter::new().label_lossy("in\0side")),
+            Err(Error::LabelContainsNul)
+        ));
+
+        let full = SwapWriter::new().label_bytes(&[b'a'; 16]).unwrap();
+        let (page, _) = build(full).unwrap();
+        assert_eq!(&page[1024 + 28..1024 + 44], &[b'a'; 16]);
+        let full = SwapWriter::new().label_bytes(&[b'a'; 16]).unwrap();
+        assert!(matches!(
+            build(full.label_terminator(true)),
+            Err(Error::LabelTooLong)
+        ));
+        let (page, summary) = build(
+            SwapWriter::new()
+                .label_terminator(true)
+                .label_lossy("sixteen-byte-lbl"),
+        )
+        .unwrap();
+        assert_eq!(&page[1024 + 28..1024 + 44], b"sixteen-byte-lb\0");
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::LabelTruncated {
+                length: 16,
+                kept: 15
+            }]
+        );
+    }
+
+    #[test]
+    fn label_lossy() {
+        let build = |writer: SwapWriter| {
+            writer
+                .page_size(4096)
+                .size(40 * 4096)
+                .uuid_bytes([7; 16])
+                .build_header()
+                .unwrap()
+        };
+
+        // The 'é' would straddle the 16th byte.
+        let (page, summary) = build(SwapWriter::new().label_lossy("fifteen-bytes-xé-host"));
+        assert_eq!(&page[1024 + 28..1024 + 44], b"fifteen-bytes-x\0");
+        assert_eq!(summary.label.as_deref(), Some("fifteen-bytes-x"));
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::LabelTruncated {
+                length: 22,
+                kept: 15
+            }]
+        );
+
+        let (_, summary) = build(SwapWriter::new().label_lossy("short"));
+        assert_eq!(summary.label.as_deref(), Some("short"));
+        assert_eq!(summary.warnings, vec![]);
+        let (_, summary) = build(
+            SwapWriter::new()
+                .label_lossy("a label which is far too long")
+                .label("short".into())
+                .unwrap(),
+        );
+        assert_eq!(summary.warnings, vec![]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Detect hibernation images stored in a swap space.
+
+use std::io::{SeekFrom, Write};
+
+use uuid::Uuid;
+
+use crate::probe::find_magic;
+use crate::{Error, ReadSeek, SWAPSPACE2_MAGIC};
+
+/// A signature written in place of the swap magic by a hibernation
+/// implementation, marking that the swap space holds a hibernation image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HibernationSignature {
+    /// `S1SUSPEND`, written by the kernel's built-in hibernation.
+    S1Suspend,
+    /// `S2SUSPEND`, written by older kernels' built-in hibernation.
+    S2Suspend,
+    /// `ULSUSPEND`, written by the userspace uswsusp tools.
+    UlSuspend,
+    /// `LINHIB0001`.
+    LinHib0001,
+    /// The binary TuxOnIce signature.
+    TuxOnIce,
+}
+
+impl HibernationSignature {
+    /// The bytes written at the end of the first page for this signature.
+    pub fn magic(self) -> &'static [u8] {
+        match self {
+            HibernationSignature::S1Suspend => b"S1SUSPEND",
+            HibernationSignature::S2Suspend => b"S2SUSPEND",
+            HibernationSignature::UlSuspend => b"ULSUSPEND",
+            HibernationSignature::LinHib0001 => b"LINHIB0001",
+            HibernationSignature::TuxOnIce => b"\xed\xc3\x02\xe9\x98\x56\xe5\x0c",
+        }
+    }
+
+    pub(crate) fn recognize(magic: &[u8; 10]) -> Option<Self> {
+        [
+            HibernationSignature::S1Suspend,
+            HibernationSignature::S2Suspend,
+            HibernationSignature::UlSuspend,
+            HibernationSignature::LinHib0001,
+            HibernationSignature::TuxOnIce,
+        ]
+        .into_iter()
+        .find(|signature| magic.starts_with(signature.magic()))
+    }
+}
+
+/// Whether [`clear_hibernation`] may clear a signature for an image which
+/// hasn't been resumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClearPolicy {
+    /// Clear any hibernation signature.
+    Always,
+    /// Refuse to clear a signature which looks like a live image, returning
+    /// [`Error::HibernationImageLive`].
+    UnlessLive,
+}
+
+/// Check whether the handle holds a hibernation image, in place of the
+/// `SWAPSPACE2` magic.
+///
+/// As with [`probe`](crate::probe), the magic is checked for each of the
+/// common page sizes.
+pub fn detect_hibernation<T: ReadSeek>(handle: T) -> Result<Option<HibernationSignature>, Error> {
+    let found = find_magic(handle, HibernationSignature::recognize)?;
+
+    Ok(found.map(|(signature, _)| signature))
+}
+
+/// Restore the `SWAPSPACE2` magic over a hibernation signature, as `swapon`
+/// does before activating a swap space.
+///
+/// The image is considered live if the kernel's hibernation header
+/// points at image data, or if the header layout isn't known (TuxOnIce).
+///
+/// Returns the signature which was cleared, or None if there wasn't one.
+pub fn clear_hibernation<T: ReadSeek + Write>(
+    mut handle: T,
+    policy: ClearPolicy,
+) -> Result<Option<HibernationSignature>, Error> {
+    let (signature, page_size) = match find_magic(&mut handle, HibernationSignature::recognize)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    if policy == ClearPolicy::UnlessLive && is_live(&mut handle, signature, page_size)? {
+        return Err(Error::HibernationImageLive(signature));
+    }
+
+    handle
+        .seek(SeekFrom::Start(page_size - 10))
+        .map_err(Error::WriteHeader)?;
+    handle
+        .write_all(SWAPSPACE2_MAGIC)
+        .map_err(Error::WriteHeader)?; // magic
+    handle
+        .seek(SeekFrom::Start(0))
+        .map_err(Error::WriteHeader)?;
+
+    Ok(Some(signature))
+}
+
+/// The kernel command line parameters for resuming from a swap space.
+///
+/// For a swap partition, `uuid` is the swap space's own UUID, as in
+/// [`WriteSummary::uuid`](crate::WriteSummary::uuid), and there is no
+/// `resume_offset`. For a swap file, `uuid` is the UUID of the filesystem
+/// holding it, and `resume_offset` comes from
+/// [`resume_offset`](crate::resume_offset).
+pub fn resume_cmdline(uuid: Uuid, resume_offset: Option<u64>) -> String {
+    match resume_offset {
+        Some(offset) => format!("resume=UUID={} resume_offset={}", uuid, offset),
+        None => format!("resume=UUID={}", uuid),
+    }
+}
+
+/// Check the `image` field of the kernel's `struct swsusp_header`, which
+/// sits just before the flags and the original and current signatures.
+fn is_live<T: ReadSeek>(
+    mut handle: T,
+    signature: HibernationSignature,
+    page_size: u64,
+) -> Result<bool, Error> {
+    if signature == HibernationSignature::TuxOnIce {
+        return Ok(true);
+    }
+
+    let mut image = [0; 8];
+    handle
+        .seek(SeekFrom::Start(page_size - 32))
+        .map_err(Error::ReadHeader)?;
+    handle.read_exact(&mut image).map_err(Error::ReadHeader)?;
+
+    Ok(image != [0; 8])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn detect_signatures() {
+        assert_eq!(
+            detect_hibernation(Cursor::new(vec![0; 8192])).unwrap(),
+            None
+        );
+
+        let mut bytes = vec![0; 16384];
+        bytes[8182..8191].copy_from_slice(b"S1SUSPEND");
+        assert_eq!(
+            detect_hibernation(Cursor::new(bytes)).unwrap(),
+            Some(HibernationSignature::S1Suspend)
+        );
+
+        let mut bytes = vec![0; 4096];
+        bytes[4086..4094].copy_from_slice(HibernationSignature::TuxOnIce.magic());
+        assert_eq!(
+            detect_hibernation(Cursor::new(bytes)).unwrap(),
+            Some(HibernationSignature::TuxOnIce)
+        );
+    }
+    #[test]
+    fn clear_signatures() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        crate::SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            clear_hibernation(&mut buffer, ClearPolicy::UnlessLive).unwrap(),
+            None
+        );
+
+        buffer.get_mut()[4086..4096].copy_from_slice(b"S1SUSPEND\0");
+        buffer.get_mut()[4064] = 1;
+        assert!(matches!(
+            clear_hibernation(&mut buffer, ClearPolicy::UnlessLive),
+            Err(Error::HibernationImageLive(HibernationSignature::S1Suspend))
+        ));
+        assert_eq!(
+            clear_hibernation(&mut buffer, ClearPolicy::Always).unwrap(),
+            Some(HibernationSignature::S1Suspend)
+        );
+        assert_eq!(&buffer.get_ref()[4086..4096], b"SWAPSPACE2");
+    }
+
+    #[test]
+    fn cmdline() {
+        let uuid = Uuid::from_u128(0x0657fd6d_a4ab_43c4_84e5_0933c84b4f4f);
+        assert_eq!(
+            resume_cmdline(uuid, None),
+            "resume=UUID=0657fd6d-a4ab-43c4-84e5-0933c84b4f4f"
+        );
+        assert_eq!(
+            resume_cmdline(uuid, Some(34816)),
+            "resume=UUID=0657fd6d-a4ab-43c4-84e5-0933c84b4f4f resume_offset=34816"
+        );
+    }
+}
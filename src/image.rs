@@ -0,0 +1,435 @@
+//! Build whole-disk images holding a swap partition.
+
+use std::fs::OpenOptions;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use uuid::Uuid;
+
+use crate::{Error, SwapWriter, SyncAll, WriteSeek, WriteSummary, GPT_SWAP_TYPE, MBR_SWAP_TYPE};
+
+/// Where the swap partition starts, for the alignment partitioning tools use.
+const PARTITION_ALIGNMENT_BYTES: u64 = 1024 * 1024;
+/// The number of GPT partition entries, and the size of each.
+const GPT_ENTRIES: u64 = 128;
+const GPT_ENTRY_BYTES: u64 = 128;
+
+/// The kind of partition table [`ImageBuilder`] creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartitionScheme {
+    /// A GPT partition table, behind a protective MBR.
+    #[default]
+    Gpt,
+    /// An MBR partition table, limited to 2^32 sectors.
+    Mbr,
+}
+
+/// What [`ImageBuilder`] wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSummary {
+    /// The size of the whole image in bytes.
+    pub image_bytes: u64,
+    /// The offset of the swap partition in bytes, from the start of the image.
+    pub offset: u64,
+    /// The size of the swap partition in bytes.
+    pub size: u64,
+    /// The GPT disk GUID. Always None for MBR images.
+    pub disk_guid: Option<Uuid>,
+    /// The GPT partition GUID of the swap partition, its PARTUUID. Always
+    /// None for MBR images.
+    pub partition_guid: Option<Uuid>,
+    /// The swap space written into the partition.
+    pub swap: WriteSummary,
+}
+
+/// A builder to create a disk image with a partition table and a single
+/// swap partition, such as for a VM image pipeline.
+pub struct ImageBuilder {
+    size: u64,
+    scheme: PartitionScheme,
+    sector_size: u64,
+    disk_guid: Option<Uuid>,
+    partition_guid: Option<Uuid>,
+    writer: SwapWriter,
+}
+
+impl ImageBuilder {
+    /// Construct an ImageBuilder for a swap partition of `size` bytes
+    ///
+    /// The size is rounded up to whole sectors. It must hold at least
+    /// [`MINIMUM_PAGES`](crate::MINIMUM_PAGES), or creating the image fails
+    /// before anything is written.
+    pub fn new(size: u64) -> Self {
+        Self {
+            size,
+            scheme: PartitionScheme::Gpt,
+            sector_size: 512,
+            disk_guid: None,
+            partition_guid: None,
+            writer: SwapWriter::new(),
+        }
+    }
+
+    /// Specify the kind of partition table. Defaults to [`PartitionScheme::Gpt`].
+    pub fn scheme(mut self, scheme: PartitionScheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Specify the logical sector size of the disk. Defaults to 512.
+    pub fn sector_size(mut self, sector_size: u64) -> Self {
+        self.sector_size = sector_size;
+        self
+    }
+
+    /// Specify the GPT disk GUID. A random one is generated otherwise.
+    pub fn disk_guid(mut self, disk_guid: Uuid) -> Self {
+        self.disk_guid = Some(disk_guid);
+        self
+    }
+
+    /// Specify the GPT partition GUID of the swap partition. A random one is
+    /// generated otherwise.
+    pub fn partition_guid(mut self, partition_guid: Uuid) -> Self {
+        self.partition_guid = Some(partition_guid);
+        self
+    }
+
+    /// Specify how the swap space is written, like its label or page size
+    ///
+    /// Its offset and size are replaced with the swap partition's.
+    pub fn swap(mut self, writer: SwapWriter) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    /// Create a new image file, which must not already exist, and write the
+    /// partition table and swap space to it.
+    pub fn create<P: AsRef<Path>>(self, path: P) -> Result<ImageSummary, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(Error::CreateFile)?;
+
+        let result = self
+            .image_bytes()
+            .and_then(|image_bytes| file.set_len(image_bytes).map_err(Error::Allocate))
+            .and_then(|()| self.write(&mut file))
+            .and_then(|summary| {
+                file.sync_all().map_err(Error::WriteHeader)?;
+                Ok(summary)
+            });
+        if result.is_err() {
+            // Don't leave a half-created image behind.
+            let _ = std::fs::remove_file(&path);
+        }
+        result
+    }
+
+    /// Write the partition table and swap space to a zeroed handle, like a
+    /// new file. A handle shorter than [`ImageSummary::image_bytes`] is grown
+    /// by writing its last byte.
+    pub fn write<T: WriteSeek + SyncAll>(self, mut handle: T) -> Result<ImageSummary, Error> {
+        let sector_size = self.sector_size;
+        let image_bytes = self.image_bytes()?;
+        let end = handle
+            .seek(SeekFrom::End(0))
+            .map_err(Error::SizeDetection)?;
+        if end < image_bytes {
+            handle
+                .seek(SeekFrom::Start(image_bytes - 1))
+                .map_err(Error::Allocate)?;
+            handle.write_all(&[0]).map_err(Error::Allocate)?;
+        }
+        let total_sectors = image_bytes / sector_size;
+        let first_lba = PARTITION_ALIGNMENT_BYTES / sector_size;
+        let last_lba = first_lba
+            .checked_add(self.size.div_ceil(sector_size))
+            .and_then(|end| end.checked_sub(1))
+            .filter(|&last_lba| last_lba >= first_lba)
+            .ok_or(Error::InvalidLbaRange)?;
+
+        let (disk_guid, partition_guid) = match self.scheme {
+            PartitionScheme::Gpt => {
+                let disk_guid = self.disk_guid.unwrap_or_else(Uuid::new_v4);
+                let partition_guid = self.partition_guid.unwrap_or_else(Uuid::new_v4);
+                let table = GptTable {
+                    sector_size,
+                    total_sectors,
+                    disk_guid,
+                    partition_guid,
+                    first_lba,
+                    last_lba,
+                };
+                table.write(&mut handle)?;
+                (Some(disk_guid), Some(partition_guid))
+            }
+            PartitionScheme::Mbr => {
+                let first = u32::try_from(first_lba).map_err(|_| Error::InvalidLbaRange)?;
+                let sectors =
+                    u32::try_from(last_lba - first_lba + 1).map_err(|_| Error::InvalidLbaRange)?;
+                write_mbr(&mut handle, MBR_SWAP_TYPE, first, sectors)?;
+                (None, None)
+            }
+        };
+
+        let swap = self
+            .writer
+            .lba_range(first_lba, last_lba, sector_size)?
+            .write(&mut handle)?;
+
+        Ok(ImageSummary {
+            image_bytes,
+            offset: first_lba * sector_size,
+            size: (last_lba - first_lba + 1) * sector_size,
+            disk_guid,
+            partition_guid,
+            swap,
+        })
+    }
+
+    /// The size of the whole image: the aligned start of the partition, the
+    /// partition, and the backup GPT at the end of the disk.
+    ///
+    /// Fails if the partition can't hold a swap space, so nothing is written.
+    fn image_bytes(&self) -> Result<u64, Error> {
+        if !self.sector_size.is_power_of_two() || !(512..=4096).contains(&self.sector_size) {
+            return Err(Error::InvalidSectorSize(self.sector_size));
+        }
+        let page_size = self.writer.resolve_page_size()?;
+        let minimum = self.writer.minimum_pages();
+        let pages = self.size / page_size;
+        if pages < u64::from(minimum) {
+            return Err(Error::TooFewPages(
+                pages as u32,
+                u64::from(minimum) * page_size,
+            ));
+        }
+        let backup_bytes = match self.scheme {
+            PartitionScheme::Gpt => {
+                gpt_entries_sectors(self.sector_size) * self.sector_size + self.sector_size
+            }
+            PartitionScheme::Mbr => 0,
+        };
+        self.size
+            .div_ceil(self.sector_size)
+            .checked_mul(self.sector_size)
+            .and_then(|size| size.checked_add(PARTITION_ALIGNMENT_BYTES + backup_bytes))
+            .ok_or(Error::InvalidLbaRange)
+    }
+}
+
+/// The number of sectors holding the GPT partition entries.
+fn gpt_entries_sectors(sector_size: u64) -> u64 {
+    (GPT_ENTRIES * GPT_ENTRY_BYTES).div_ceil(sector_size)
+}
+
+/// Write the sector of an MBR with a single partition.
+fn write_mbr<T: WriteSeek>(
+    mut handle: T,
+    partition_type: u8,
+    first_lba: u32,
+    sectors: u32,
+) -> Result<(), Error> {
+    let mut sector = [0; 512];
+    let entry = &mut sector[446..462];
+    // The CHS addresses are unused by LBA-aware systems, and set to their
+    // maximum like other partitioning tools do.
+    entry[1..4].copy_from_slice(&[0xfe, 0xff, 0xff]); // first CHS
+    entry[4] = partition_type; // partition type
+    entry[5..8].copy_from_slice(&[0xfe, 0xff, 0xff]); // last CHS
+    entry[8..12].copy_from_slice(&first_lba.to_le_bytes()); // first LBA
+    entry[12..16].copy_from_slice(&sectors.to_le_bytes()); // sectors
+    sector[510..512].copy_from_slice(&[0x55, 0xaa]); // boot signature
+
+    handle
+        .seek(SeekFrom::Start(0))
+        .map_err(Error::WriteHeader)?;
+    handle.write_all(&sector).map_err(Error::WriteHeader)?;
+
+    Ok(())
+}
+
+/// A GPT with a single swap partition.
+struct GptTable {
+    sector_size: u64,
+    total_sectors: u64,
+    disk_guid: Uuid,
+    partition_guid: Uuid,
+    first_lba: u64,
+    last_lba: u64,
+}
+
+impl GptTable {
+    /// Write the protective MBR, and the primary and backup headers and
+    /// partition entries.
+    fn write<T: WriteSeek>(&self, mut handle: T) -> Result<(), Error> {
+        let sectors = u32::try_from(self.total_sectors - 1).unwrap_or(u32::MAX);
+        write_mbr(&mut handle, 0xee, 1, sectors)?;
+
+        let entries_sectors = gpt_entries_sectors(self.sector_size);
+        let mut entries = vec![0; (entries_sectors * self.sector_size) as usize];
+        let entry = &mut entries[..GPT_ENTRY_BYTES as usize];
+        entry[0..16].copy_from_slice(&GPT_SWAP_TYPE.to_bytes_le()); // partition type GUID
+        entry[16..32].copy_from_slice(&self.partition_guid.to_bytes_le()); // unique partition GUID
+        entry[32..40].copy_from_slice(&self.first_lba.to_le_bytes()); // first LBA
+        entry[40..48].copy_from_slice(&self.last_lba.to_le_bytes()); // last LBA
+        for (i, unit) in "swap".encode_utf16().enumerate() {
+            entry[56 + 2 * i..58 + 2 * i].copy_from_slice(&unit.to_le_bytes()); // partition name
+        }
+        let entries_crc = crc32(&entries[..(GPT_ENTRIES * GPT_ENTRY_BYTES) as usize]);
+
+        let last_lba = self.total_sectors - 1;
+        let backup_entries_lba = last_lba - entries_sectors;
+        for (header_lba, alternate_lba, entries_lba) in
+            [(1, last_lba, 2), (last_lba, 1, backup_entries_lba)]
+        {
+            let header = self.header(header_lba, alternate_lba, entries_lba, entries_crc);
+            for (lba, bytes) in [(header_lba, &header[..]), (entries_lba, &entries[..])] {
+                handle
+                    .seek(SeekFrom::Start(lba * self.sector_size))
+                    .map_err(Error::WriteHeader)?;
+                handle.write_all(bytes).map_err(Error::WriteHeader)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn header(
+        &self,
+        header_lba: u64,
+        alternate_lba: u64,
+        entries_lba: u64,
+        entries_crc: u32,
+    ) -> Vec<u8> {
+        let entries_sectors = gpt_entries_sectors(self.sector_size);
+        let mut header = vec![0; self.sector_size as usize];
+        let mut field = |offset: usize, bytes: &[u8]| {
+            header[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        field(0, b"EFI PART"); // signature
+        field(8, &0x0001_0000u32.to_le_bytes()); // revision
+        field(12, &92u32.to_le_bytes()); // header size
+        field(24, &header_lba.to_le_bytes()); // current LBA
+        field(32, &alternate_lba.to_le_bytes()); // backup LBA
+        field(40, &(2 + entries_sectors).to_le_bytes()); // first usable LBA
+        field(
+            48,
+            &(self.total_sectors - 2 - entries_sectors).to_le_bytes(),
+        ); // last usable LBA
+        field(56, &self.disk_guid.to_bytes_le()); // disk GUID
+        field(72, &entries_lba.to_le_bytes()); // partition entries LBA
+        field(80, &(GPT_ENTRIES as u32).to_le_bytes()); // number of partition entries
+        field(84, &(GPT_ENTRY_BYTES as u32).to_le_bytes()); // size of a partition entry
+        field(88, &entries_crc.to_le_bytes()); // partition entries CRC32
+        let header_crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes()); // header CRC32
+
+        header
+    }
+}
+
+/// The CRC-32 used by GPT, as in zlib.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{detect_partition_table, PartitionTable, SwapReader};
+    use std::io::Cursor;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn gpt_image() {
+        let disk_guid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut image = Cursor::new(Vec::new());
+        let summary = ImageBuilder::new(40 * 1024)
+            .disk_guid(disk_guid)
+            .swap(SwapWriter::new().page_size(4096))
+            .write(&mut image)
+            .unwrap();
+        assert_eq!(summary.offset, 1024 * 1024);
+        assert_eq!(summary.size, 40 * 1024);
+        assert_eq!(summary.image_bytes, 1024 * 1024 + 40 * 1024 + 33 * 512);
+        assert_eq!(summary.disk_guid, Some(disk_guid));
+        assert_eq!(image.get_ref().len() as u64, summary.image_bytes);
+
+        let bytes = image.get_ref();
+        assert_eq!(&bytes[512..520], b"EFI PART");
+        assert_eq!(&bytes[bytes.len() - 512..bytes.len() - 504], b"EFI PART");
+        assert_eq!(
+            detect_partition_table(&mut image).unwrap(),
+            Some(PartitionTable::Gpt)
+        );
+
+        let mut swap = Cursor::new(image.into_inner()[1024 * 1024..].to_vec());
+        let header = SwapReader::new().read(&mut swap).unwrap();
+        assert_eq!(Some(header.uuid), summary.swap.uuid);
+    }
+
+    #[test]
+    fn mbr_image() {
+        let mut image = Cursor::new(Vec::new());
+        let summary = ImageBuilder::new(40 * 1024)
+            .scheme(PartitionScheme::Mbr)
+            .swap(SwapWriter::new().page_size(4096))
+            .write(&mut image)
+            .unwrap();
+        assert_eq!(summary.image_bytes, 1024 * 1024 + 40 * 1024);
+        assert_eq!(summary.partition_guid, None);
+        assert_eq!(
+            crate::mbr_partition_type(&mut image, 0).unwrap(),
+            MBR_SWAP_TYPE
+        );
+        assert_eq!(&image.get_ref()[446 + 8..446 + 12], &2048u32.to_le_bytes());
+    }
+
+    #[test]
+    fn too_small() {
+        for scheme in [PartitionScheme::Gpt, PartitionScheme::Mbr] {
+            for size in [0, 4095] {
+                let mut image = Cursor::new(Vec::new());
+                assert!(matches!(
+                    ImageBuilder::new(size)
+                        .scheme(scheme)
+                        .swap(SwapWriter::new().page_size(4096).force(true))
+                        .write(&mut image),
+                    Err(Error::TooFewPages(0, _))
+                ));
+                assert!(image.get_ref().is_empty());
+            }
+        }
+    }
+
+    #[cfg(feature = "gpt")]
+    #[test]
+    fn gpt_crate_reads_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.img");
+        let summary = ImageBuilder::new(1024 * 1024).create(&path).unwrap();
+
+        let disk = gpt::GptConfig::new().writable(false).open(&path).unwrap();
+        let partitions: Vec<_> = disk.partitions().values().collect();
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(
+            partitions[0].part_type_guid,
+            gpt::partition_types::LINUX_SWAP
+        );
+        assert_eq!(Some(partitions[0].part_guid), summary.partition_guid);
+        assert_eq!(partitions[0].first_lba, 2048);
+        assert_eq!(*disk.guid(), summary.disk_guid.unwrap());
+    }
+}
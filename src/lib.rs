@@ -23,45 +23,389 @@
 //! ```
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use std::io::Cursor;
 //!
 //! use mkswap::SwapWriter;
 //!
 //! let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
-//! let size = SwapWriter::new()
+//! let summary = SwapWriter::new()
 //!     .label("🔀".into())
 //!     .unwrap()
 //!     .write(&mut buffer)
 //!     .unwrap();
-//!
+//! # }
 //! ```
 //!
 //! ### Notes
 //!
 //! This library will seek around the file, including back to position 0.
-//! If this isn't desirable, consider use fscommon::StreamSlice or sending
-//! a pull request.
+//! To format a swap space inside a larger image, see [`SwapWriter::offset`],
+//! which confines the seeks to the swap space.
+//!
+//! Outside Linux, such as on wasm32-wasi, the APIs for devices, swap files
+//! and the running system are unavailable, but swap spaces can still be
+//! written to any handle, including WASI's files.
+//!
+//! ### no_std
+//!
+//! Without the default `std` feature, the crate is `no_std`, and only needs
+//! `alloc`. The header can then only be built in memory, with
+//! [`SwapWriter::build_header`], for the caller to write out.
+//!
+//! The `uuid` feature, which `std` implies, brings in the uuid crate to
+//! generate random UUIDs. Without it, the UUID must be given as bytes:
+//!
+//! ```rust
+//! use mkswap::SwapWriter;
+//!
+//! let (page, summary) = SwapWriter::new()
+//!     .page_size(4096)
+//!     .size(40 * 1024)
+//!     .uuid_bytes([0x42; 16])
+//!     .build_header()
+//!     .unwrap();
+//! assert_eq!(page.len(), 4096);
+//! ```
 
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
-use std::io::{Seek, SeekFrom, Write};
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(feature = "uuid")]
 use uuid::Uuid;
 
+#[cfg(feature = "std")]
+use header::{write_region, Header, Layout};
+#[cfg(feature = "std")]
+use slice::Slice;
+
+mod arch;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod asynchronous;
+#[cfg(feature = "std")]
+mod backup;
+#[cfg(feature = "std")]
+mod blkid;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(all(feature = "crypt", target_os = "linux"))]
+mod crypt;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod device;
+#[cfg(feature = "std")]
+mod edit;
+#[cfg(all(feature = "swapon", target_os = "linux"))]
+mod enable;
+#[cfg(feature = "std")]
+mod erase;
+#[cfg(all(feature = "ffi", target_os = "linux"))]
+pub mod ffi;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod file;
+mod header;
+pub use header::HeaderField;
+#[cfg(feature = "std")]
+mod hibernation;
+#[cfg(feature = "std")]
+mod image;
+#[cfg(feature = "std")]
+mod partition;
+#[cfg(feature = "std")]
+mod probe;
+mod progress;
+#[cfg(feature = "std")]
+mod reader;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod resume;
+#[cfg(feature = "std")]
+mod scan;
+#[cfg(feature = "std")]
+mod signature;
+#[cfg(feature = "std")]
+mod size;
+#[cfg(feature = "std")]
+mod slice;
+#[cfg(all(feature = "swapon", target_os = "linux"))]
+mod swapon;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod system;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "uuid")]
+mod uuid_source;
+mod warning;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod zram;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod zswap;
+pub use arch::{max_swap_bytes, Arch, ArchLimitPolicy, TargetProfile};
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use asynchronous::AsyncSwapWriter;
+#[cfg(feature = "std")]
+pub use backup::{backup, restore};
+#[cfg(feature = "std")]
+pub use blkid::{blkid_tags, BlkidTag, BlkidTags};
+#[cfg(feature = "std")]
+pub use config::{
+    CryptDevice, CrypttabEntry, Discard, FstabEntry, StripePlan, SwapUnit, MAXIMUM_PRIORITY,
+};
+#[cfg(all(feature = "crypt", target_os = "linux"))]
+pub use crypt::{CryptSummary, CryptSwap};
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use device::{device_size, open_device, LockMode};
+#[cfg(feature = "std")]
+pub use edit::{fix_page_size, SwapEditor};
+#[cfg(all(feature = "swapon", target_os = "linux"))]
+pub use enable::EnableSummary;
+#[cfg(feature = "std")]
+pub use erase::{erase, ErasePattern};
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use file::{create_swap_file, Prealloc};
+#[cfg(feature = "std")]
+pub use hibernation::{
+    clear_hibernation, detect_hibernation, resume_cmdline, ClearPolicy, HibernationSignature,
+};
+#[cfg(feature = "std")]
+pub use image::{ImageBuilder, ImageSummary, PartitionScheme};
+#[cfg(feature = "std")]
+pub use partition::{mbr_partition_type, set_mbr_partition_type, GPT_SWAP_TYPE, MBR_SWAP_TYPE};
+#[cfg(feature = "std")]
+pub use probe::probe;
+pub use progress::{Phase, Progress};
+#[cfg(feature = "std")]
+pub use reader::{SwapHeader, SwapReader};
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use resume::resume_offset;
+#[cfg(all(feature = "hibernate", target_os = "linux"))]
+pub use resume::set_resume;
+#[cfg(feature = "std")]
+pub use scan::parse_badblocks;
+#[cfg(feature = "std")]
+pub use signature::{
+    detect_partition_table, detect_signatures, wipe_signatures, FoundSignature, PartitionTable,
+    Signature,
+};
+#[cfg(feature = "std")]
+pub use size::{parse_size, recommend_size, SizePolicy};
+#[cfg(all(feature = "swapon", target_os = "linux"))]
+pub use swapon::{swapoff, swapon, SwapOnOptions};
+#[cfg(feature = "uuid")]
+pub use uuid_source::{CounterUuids, FixedUuid, RandomUuids, SeededUuids, UuidSource, UuidSpec};
+pub use warning::Warning;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use zram::{ZramSummary, ZramSwap};
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use zswap::{zswap_parameters, ZswapConfig, ZswapParameters};
+
 const MAXIMUM_LABEL_BYTES: usize = 16;
-const MINIMUM_PAGES: u32 = 10;
+/// The fewest pages a swap space can have, including the header page, like
+/// util-linux. See [`SwapWriter::force`] to go lower.
+pub const MINIMUM_PAGES: u32 = 10;
+/// The fewest pages a forced swap space can have: the header page, and one
+/// page to swap to.
+const FORCED_MINIMUM_PAGES: u32 = 2;
+/// The page size used when none is specified, without the `host-page-size`
+/// feature or on WebAssembly: that of x86_64 and most aarch64 kernels.
+pub const DEFAULT_PAGE_SIZE: u64 = 4096;
+const MINIMUM_PAGE_SIZE: u64 = 2048;
+const MAXIMUM_PAGE_SIZE: u64 = 1024 * 1024 * 1024;
+const BOOTBITS_BYTES: u64 = 1024;
+const BADPAGES_OFFSET: u64 = BOOTBITS_BYTES + 512;
+/// The end of the fixed header fields, after the label.
+#[cfg(feature = "std")]
+const HEADER_END: u64 = BOOTBITS_BYTES + 28 + MAXIMUM_LABEL_BYTES as u64;
+const SWAPSPACE2_MAGIC: &[u8; 10] = b"SWAPSPACE2";
+const SWAP_SPACE_MAGIC: &[u8; 10] = b"SWAP-SPACE";
 
+#[cfg(feature = "std")]
 /// A general wrapper to merge std::io::Write and std::io::Seek.
 pub trait WriteSeek: Write + Seek {}
+#[cfg(feature = "std")]
 impl<T: Write + Seek> WriteSeek for T {}
 
+#[cfg(feature = "std")]
+/// A general wrapper to merge std::io::Read and std::io::Seek.
+pub trait ReadSeek: Read + Seek {}
+#[cfg(feature = "std")]
+impl<T: Read + Seek> ReadSeek for T {}
+
+#[cfg(feature = "std")]
+/// A handle which can be synced to its storage, by [`SwapWriter::sync`].
+///
+/// Files are synced with `sync_all`. Handles without storage of their own,
+/// like buffers, use the default, which only flushes them: implement it for
+/// other handles with `impl SyncAll for MyHandle {}`.
+pub trait SyncAll: Write {
+    /// Flush the handle, and sync what was written to its storage.
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+#[cfg(feature = "std")]
+impl SyncAll for std::fs::File {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        std::fs::File::sync_all(self)
+    }
+}
+#[cfg(feature = "std")]
+impl SyncAll for &std::fs::File {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        std::fs::File::sync_all(self)
+    }
+}
+#[cfg(feature = "std")]
+impl<T: SyncAll + ?Sized> SyncAll for &mut T {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        (**self).sync_all()
+    }
+}
+#[cfg(feature = "std")]
+impl<T: SyncAll + ?Sized> SyncAll for Box<T> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        (**self).sync_all()
+    }
+}
+#[cfg(feature = "std")]
+impl<W: SyncAll> SyncAll for std::io::BufWriter<W> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_all()
+    }
+}
+#[cfg(feature = "std")]
+impl<T> SyncAll for std::io::Cursor<T> where std::io::Cursor<T>: Write {}
+#[cfg(feature = "std")]
+impl SyncAll for Vec<u8> {}
+
+/// The byte order multi-byte header fields are encoded with.
+///
+/// The kernel reads the header in its own byte order, so this must match
+/// the architecture the swap space will be used on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    /// The byte order of the system running this code.
+    #[default]
+    Native,
+    /// Little endian, as on x86_64, aarch64 and ppc64le.
+    Little,
+    /// Big endian, as on s390x and ppc64.
+    Big,
+}
+
+impl Endianness {
+    fn u32_to_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endianness::Native => value.to_ne_bytes(),
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn u32_from_bytes(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Native => u32::from_ne_bytes(bytes),
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// The format of a swap space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapVersion {
+    /// The legacy `SWAP-SPACE` format: the first page is a bitmap of usable
+    /// pages. Removed from Linux in 2.5.
+    V0,
+    /// The current `SWAPSPACE2` format, with a header holding the last page,
+    /// bad pages, UUID and label.
+    V1,
+}
+
+/// What [`SwapWriter::write`] wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// The swap space's UUID. Always None for V0 swap spaces.
+    #[cfg(feature = "uuid")]
+    pub uuid: Option<Uuid>,
+    /// The swap space's label, if one was set.
+    pub label: Option<String>,
+    /// The page size the swap space was written for.
+    pub page_size: u64,
+    /// The number of pages in the swap space, including the header page and
+    /// bad pages.
+    pub pages: u32,
+    /// The usable size of the swap space in bytes, excluding bad pages.
+    pub usable_bytes: u64,
+    /// Anything surprising which was done while writing, like wiping an old
+    /// signature.
+    pub warnings: Vec<Warning>,
+}
+
+/// What to write to the bootbits, the first 1024 bytes of a V1 swap space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BootBits {
+    /// Leave the bootbits untouched, preserving a disklabel or boot loader
+    /// stored there, but also any garbage.
+    #[default]
+    Preserve,
+    /// Zero the bootbits, so the header region is fully deterministic.
+    Zero,
+}
+
+/// What to do when a swap space has more pages than the header's u32 page
+/// count can describe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClampPolicy {
+    /// Refuse to write the swap space with [`Error::SizeTooLarge`].
+    #[default]
+    Error,
+    /// Use only the pages the header can describe, and say so in
+    /// [`WriteSummary::warnings`].
+    ClampWithWarning,
+}
+
 /// A builder to construct a swap space.
 ///
 /// None of these fields are mandatory: they can all be generated.
 pub struct SwapWriter {
-    uuid: Option<Uuid>,
-    label: Option<String>,
+    uuid: Option<[u8; 16]>,
+    label: Option<Vec<u8>>,
+    /// Whether label_lossy set the label, which is truncated as it's written.
+    lossy_label: bool,
+    label_terminator: bool,
     page_size: Option<u64>,
-    size: Option<u64>,
+    size: Option<Size>,
+    bad_pages: Vec<u32>,
+    endianness: Endianness,
+    version: SwapVersion,
+    bootbits: BootBits,
+    zero_first_page: bool,
+    clamp: ClampPolicy,
+    arch_limit: Option<(Arch, ArchLimitPolicy)>,
+    #[cfg(feature = "std")]
+    offset: u64,
+    force: bool,
+    #[cfg(feature = "std")]
+    sync: bool,
+    #[cfg(feature = "std")]
+    progress: Option<Box<dyn Progress + Send>>,
+    #[cfg(feature = "std")]
+    should_continue: Option<Box<dyn Fn() -> bool + Send>>,
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    file: file::FileOptions,
+}
+
+/// How the caller specified the size of the swap space.
+#[derive(Clone, Copy)]
+enum Size {
+    Bytes(u64),
+    Pages(u32),
 }
 
 impl SwapWriter {
@@ -71,145 +415,1152 @@ impl SwapWriter {
         Self {
             uuid: None,
             label: None,
+            lossy_label: false,
+            label_terminator: false,
             page_size: None,
             size: None,
+            bad_pages: Vec::new(),
+            endianness: Endianness::Native,
+            version: SwapVersion::V1,
+            bootbits: BootBits::Preserve,
+            zero_first_page: false,
+            clamp: ClampPolicy::Error,
+            arch_limit: None,
+            #[cfg(feature = "std")]
+            offset: 0,
+            force: false,
+            #[cfg(feature = "std")]
+            sync: false,
+            #[cfg(feature = "std")]
+            progress: None,
+            #[cfg(feature = "std")]
+            should_continue: None,
+            #[cfg(all(feature = "std", target_os = "linux"))]
+            file: file::FileOptions::default(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Construct a SwapWriter with the UUID, label and page size of an
+    /// existing swap space.
+    ///
+    /// This is useful to migrate a swap space to a new device, keeping
+    /// references to its UUID or label valid. The page size is detected by
+    /// probing.
+    pub fn from_existing<T: ReadSeek>(mut handle: T) -> Result<Self, Error> {
+        let (_, page_size) = probe::find_magic(&mut handle, |magic| {
+            (magic == SWAPSPACE2_MAGIC).then_some(())
+        })?
+        .ok_or(Error::MissingSignature)?;
+        let header = SwapReader::new().page_size(page_size).read(&mut handle)?;
+
+        let writer = Self::new().uuid(header.uuid).page_size(page_size);
+        if header.label.is_empty() {
+            Ok(writer)
+        } else {
+            writer.label_bytes(header.raw_label())
         }
     }
 
     /// Set the label.
     ///
     /// Must be fewer than MAXIMUM_LABEL_BYTES, or an error is returned.
-    pub fn label(mut self, label: String) -> Result<Self, Error> {
+    pub fn label(self, label: String) -> Result<Self, Error> {
+        self.label_bytes(label.as_bytes())
+    }
+
+    /// Set the label as raw bytes, which needn't be UTF-8, to copy a label
+    /// written by another tool.
+    ///
+    /// Must be at most MAXIMUM_LABEL_BYTES, or an error is returned. NUL
+    /// padding at the end is dropped, but a NUL within the label is refused,
+    /// as blkid would cut the label short there. The label in the
+    /// [`WriteSummary`] replaces bytes which aren't UTF-8 with U+FFFD.
+    pub fn label_bytes(mut self, label: &[u8]) -> Result<Self, Error> {
+        let label = trim_label(label)?;
         if label.len() > MAXIMUM_LABEL_BYTES {
             return Err(Error::LabelTooLong);
         }
 
-        self.label = Some(label);
+        self.label = Some(label.to_vec());
+        self.lossy_label = false;
         Ok(self)
     }
 
+    /// Set the label, truncating it to MAXIMUM_LABEL_BYTES at a character
+    /// boundary if it's longer, like a label derived from a hostname.
+    ///
+    /// A truncated label is reported with [`Warning::LabelTruncated`].
+    pub fn label_lossy(mut self, label: &str) -> Self {
+        self.label = Some(label.as_bytes().to_vec());
+        self.lossy_label = true;
+        self
+    }
+
+    /// Reserve the last byte of the label for a NUL terminator, so labels
+    /// are at most 15 bytes, for readers which expect the label to be a
+    /// C string. By default, a label may fill all 16 bytes, as the kernel
+    /// and util-linux allow.
+    pub fn label_terminator(mut self, reserve: bool) -> Self {
+        self.label_terminator = reserve;
+        self
+    }
+
+    #[cfg(feature = "uuid")]
     /// Specify the filesystem's UUID
-    pub fn uuid(mut self, uuid: Uuid) -> Self {
+    pub fn uuid(self, uuid: Uuid) -> Self {
+        self.uuid_bytes(uuid.into_bytes())
+    }
+
+    /// Specify the filesystem's UUID as its 16 bytes, in the order they're
+    /// written out, as returned by the uuid crate's `Uuid::as_bytes`.
+    ///
+    /// Without the `uuid` feature, this is the only way to specify the UUID,
+    /// and a V1 swap space can't be written without one.
+    pub fn uuid_bytes(mut self, uuid: [u8; 16]) -> Self {
         self.uuid = Some(uuid);
         self
     }
 
+    #[cfg(feature = "uuid")]
+    /// Derive the filesystem's UUID from a name, like an image's name, as a
+    /// version 5 UUID in the namespace.
+    ///
+    /// The same namespace and name always give the same UUID, so images
+    /// built with it are reproducible byte for byte.
+    pub fn uuid_v5(self, namespace: &Uuid, name: &[u8]) -> Self {
+        self.uuid(Uuid::new_v5(namespace, name))
+    }
+
+    #[cfg(all(feature = "std", feature = "uuid"))]
+    /// Generate the filesystem's UUID now as a time-ordered version 7 UUID,
+    /// instead of a random one, so swap spaces sort by when they were
+    /// created. See [`UuidSpec::TimeOrdered`].
+    pub fn uuid_v7(self) -> Self {
+        self.uuid_source(&mut UuidSpec::TimeOrdered)
+    }
+
     /// Specify the filesystem's page size
+    ///
+    /// A page size other than the running system's is written with a warning
+    /// in [`WriteSummary::warnings`], as the kernel here won't accept it.
     pub fn page_size(mut self, page_size: u64) -> Self {
         self.page_size = Some(page_size);
         self
     }
 
+    /// Specify the byte order of the header fields
+    ///
+    /// Defaults to the byte order of the running system. Set this when
+    /// building an image for an architecture with a different byte order.
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Specify the format of the swap space
+    ///
+    /// Defaults to [`SwapVersion::V1`], the only format modern kernels
+    /// support. [`SwapVersion::V0`] has no room for a label or UUID.
+    pub fn version(mut self, version: SwapVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Specify the page size and byte order together, for a swap space to be
+    /// used on another architecture
+    ///
+    /// Either can be overridden afterwards with [`SwapWriter::page_size`] or
+    /// [`SwapWriter::endianness`].
+    pub fn target(self, target: TargetProfile) -> Self {
+        self.page_size(target.page_size())
+            .endianness(target.endianness())
+    }
+
+    /// Specify what to write to the bootbits
+    ///
+    /// Defaults to [`BootBits::Preserve`]. Ignored for [`SwapVersion::V0`],
+    /// where the bootbits are part of the usable pages bitmap.
+    pub fn bootbits(mut self, bootbits: BootBits) -> Self {
+        self.bootbits = bootbits;
+        self
+    }
+
+    /// Zero the whole first page before writing the header, apart from the
+    /// bootbits which follow [`SwapWriter::bootbits`]
+    ///
+    /// Defaults to false, where only the header fields are written, and a
+    /// previous swap space's bad pages or other data can leak through past
+    /// them. Ignored for [`SwapVersion::V0`], which always writes the
+    /// whole first page.
+    pub fn zero_first_page(mut self, zero_first_page: bool) -> Self {
+        self.zero_first_page = zero_first_page;
+        self
+    }
+
+    /// Specify the size of the swap space, in bytes
+    ///
+    /// This is useful for formatting a swap space inside a larger file or
+    /// device, where the handle's size can't be used.
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(Size::Bytes(size));
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Specify where the swap space starts in the handle, in bytes
+    ///
+    /// This is useful for formatting a partition inside a whole-disk image:
+    /// the header is written relative to the offset, and all reads, writes
+    /// and seeks are confined to the swap space. If no size was specified,
+    /// the swap space runs to the end of the handle. An offset at or past
+    /// the end of the handle is refused with [`Error::SizeExceedsTarget`].
+    ///
+    /// Only used by [`SwapWriter::write`], [`SwapWriter::check`] and
+    /// [`SwapWriter::check_read_write`]. Defaults to 0.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Specify the size of the swap space as a human-readable string
+    ///
+    /// See [`parse_size`] for the accepted formats, like `512MiB` or `8G`.
+    pub fn size_str(self, size: &str) -> Result<Self, Error> {
+        Ok(self.size(parse_size(size)?))
+    }
+
+    /// Specify the size of the swap space, in pages
+    ///
+    /// The size in bytes is computed from the page size when writing.
+    /// Must be at least [`MINIMUM_PAGES`], unless [`SwapWriter::force`] was
+    /// set first, or an error is returned.
+    pub fn size_in_pages(mut self, pages: u32) -> Result<Self, Error> {
+        let minimum = self.minimum_pages();
+        if pages < minimum {
+            let page_size = self.resolve_page_size()?;
+            return Err(Error::TooFewPages(pages, u64::from(minimum) * page_size));
+        }
+
+        self.size = Some(Size::Pages(pages));
+        Ok(self)
+    }
+
+    /// Specify what to do when the size has more pages than the header can
+    /// describe
+    ///
+    /// Defaults to [`ClampPolicy::Error`], so a multi-terabyte device isn't
+    /// silently only partly used.
+    pub fn clamp(mut self, clamp: ClampPolicy) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    /// Check the size against what the kernel of an architecture can use,
+    /// see [`max_swap_bytes`]
+    ///
+    /// Defaults to no check.
+    pub fn arch_limit(mut self, arch: Arch, policy: ArchLimitPolicy) -> Self {
+        self.arch_limit = Some((arch, policy));
+        self
+    }
+
+    /// Specify pages which must not be used for swapping.
+    ///
+    /// Page numbers are validated against the last page when writing:
+    /// page 0 holds the header and can never be marked bad.
+    pub fn bad_pages(mut self, mut bad_pages: Vec<u32>) -> Self {
+        bad_pages.sort_unstable();
+        bad_pages.dedup();
+        self.bad_pages = bad_pages;
+        self
+    }
+
+    /// Skip the safety checks which refuse to overwrite a swap space or
+    /// device which is in use, or to write an impossible header. Defaults
+    /// to false.
+    ///
+    /// Forcing also lowers the minimum size from [`MINIMUM_PAGES`] to two
+    /// pages, for tests and tiny embedded targets.
+    /// [`SwapWriter::write`] otherwise refuses a specified size larger than
+    /// the handle with [`Error::SizeExceedsTarget`].
+    /// [`SwapWriter::write_file`] also refuses to overwrite an active swap
+    /// space with [`Error::SwapActive`], a mounted device with
+    /// [`Error::Mounted`], a partitioned disk with
+    /// [`Error::PartitionTableFound`] or [`Error::HasPartitions`], or any
+    /// signature recognized by [`detect_signatures`] with
+    /// [`Error::SignatureFound`].
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Sync the handle after writing the header, so it isn't lost if the
+    /// system crashes right after [`SwapWriter::write`]
+    ///
+    /// Defaults to false. A [`File`](std::fs::File) is synced with
+    /// `sync_all`, and buffers are only flushed: see [`SyncAll`].
+    /// [`SwapWriter::write_file`] and [`SwapWriter::create_file`] always sync
+    /// the file before returning.
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Report the progress of long-running operations, like scanning for bad
+    /// pages or erasing the swap space
+    ///
+    /// The callback must be Send, so the writer can be moved to another
+    /// thread or held across an `.await`.
+    pub fn progress<P: Progress + Send + 'static>(mut self, progress: P) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Check whether to continue long-running operations, cancelling them
+    /// with [`Error::Cancelled`] once this returns false
+    ///
+    /// This is checked wherever progress is reported. Scans are cancelled
+    /// between pages, after restoring any page being tested, so the swap
+    /// space's contents are left as they were. Erasing and allocating are
+    /// cancelled between chunks, leaving the swap space partly erased.
+    ///
+    /// Like [`SwapWriter::progress`], the callback must be Send.
+    pub fn should_continue<F: Fn() -> bool + Send + 'static>(mut self, should_continue: F) -> Self {
+        self.should_continue = Some(Box::new(should_continue));
+        self
+    }
+
+    #[cfg(feature = "std")]
+    /// Read every page of the swap space, marking unreadable pages as bad.
+    ///
+    /// This is the equivalent of `mkswap -c`, and should be called on the
+    /// same handle before writing. The page size and size are resolved the
+    /// same way as in [`SwapWriter::write`].
+    ///
+    /// Returns the builder with the unreadable pages added to its bad pages,
+    /// and the number of unreadable pages found.
+    pub fn check<T: ReadSeek>(mut self, handle: T) -> Result<(Self, u32), Error> {
+        let page_size = self.resolve_page_size()?;
+        let mut handle = self.slice(handle, page_size, None)?;
+        let total_size_bytes = self.resolve_size_bytes(&mut handle, page_size)?;
+        let pages = self.count_pages(total_size_bytes, page_size)?;
+
+        let found = scan::read_only(&mut handle, page_size, pages, |done, total| {
+            self.report(Phase::Scan, done, total)
+        })?;
+        Ok(self.add_bad_pages(found))
+    }
+
+    #[cfg(feature = "std")]
+    /// Read, overwrite, verify and restore every page of the swap space,
+    /// marking pages which fail any step as bad.
+    ///
+    /// This is a non-destructive read-write scan like `badblocks -n`: each
+    /// page's original contents are written back after it is tested. It is
+    /// otherwise used the same way as [`SwapWriter::check`].
+    ///
+    /// Note that the handle's reads may be served from a cache: open block
+    /// devices with `O_DIRECT` to exercise the underlying media.
+    pub fn check_read_write<T: ReadSeek + Write>(
+        mut self,
+        handle: T,
+    ) -> Result<(Self, u32), Error> {
+        let page_size = self.resolve_page_size()?;
+        let mut handle = self.slice(handle, page_size, None)?;
+        let total_size_bytes = self.resolve_size_bytes(&mut handle, page_size)?;
+        let pages = self.count_pages(total_size_bytes, page_size)?;
+
+        let found = scan::read_write(&mut handle, page_size, pages, |done, total| {
+            self.report(Phase::Scan, done, total)
+        })?;
+        Ok(self.add_bad_pages(found))
+    }
+
+    #[cfg(feature = "std")]
     /// Write the configured swap space out to a device.
     ///
     /// If no UUID was specified, a random one will be generated.
     ///
     /// If no size was specified, the size will be detected from the provided handle.
+    /// A specified size must fit in the handle, unless [`SwapWriter::force`]
+    /// is set.
     ///
-    /// If no page size was specified, the page size of the runtime system will be used.
-    pub fn write<T: WriteSeek>(self, mut handle: T) -> Result<u64, Error> {
-        let label = self.label.unwrap_or_default();
-        if label.len() > MAXIMUM_LABEL_BYTES {
-            return Err(Error::LabelTooLong);
+    /// If no page size was specified, the page size of the runtime system will be used,
+    /// or [`DEFAULT_PAGE_SIZE`] without the `host-page-size` feature.
+    ///
+    /// Returns a summary of the swap space which was written.
+    pub fn write<T: WriteSeek + SyncAll>(self, handle: T) -> Result<WriteSummary, Error> {
+        self.write_sized(handle, None)
+    }
+
+    #[cfg(feature = "std")]
+    /// Write the swap space like [`SwapWriter::write`], given the size of the
+    /// whole handle if it's already known, so it isn't sought to its end.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "write", level = "info", skip_all, fields(offset = self.offset), err)
+    )]
+    pub(crate) fn write_sized<T: WriteSeek + SyncAll>(
+        self,
+        handle: T,
+        end: Option<u64>,
+    ) -> Result<WriteSummary, Error> {
+        let page_size = self.resolve_page_size()?;
+        let mut handle = self.slice(handle, page_size, end)?;
+        let detected = match end {
+            Some(_) => Ok(handle.len()),
+            None => detect_size_bytes(&mut handle),
+        };
+        let (layout, header) = self.prepare(page_size, detected)?;
+
+        for &(start, end) in &header.regions {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                start = self.offset + start,
+                end = self.offset + end,
+                "writing the header"
+            );
+            #[cfg(feature = "log")]
+            log::debug!(
+                "writing bytes {}..{} of the swap space header",
+                self.offset + start,
+                self.offset + end
+            );
+            handle
+                .seek(SeekFrom::Start(start))
+                .map_err(Error::WriteHeader)?;
+            write_region(
+                &mut handle,
+                header.version,
+                &header.page,
+                (start, end),
+                self.offset,
+            )?;
         }
-        let uuid = self.uuid.unwrap_or_else(Uuid::new_v4);
-        let page_size = self.page_size.unwrap_or(
-            page_size::get()
-                .try_into()
-                .map_err(Error::GiganticPageSize)?,
+        handle
+            .seek(SeekFrom::Start(0))
+            .map_err(Error::WriteHeader)?;
+        if self.sync {
+            handle.sync_all().map_err(Error::WriteHeader)?;
+        }
+        #[cfg(feature = "log")]
+        let offset = self.offset;
+        let summary = self.summary(header.uuid, layout);
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            pages = summary.pages,
+            page_size = summary.page_size,
+            uuid = ?summary.uuid,
+            label = ?summary.label,
+            "wrote the swap space header"
         );
+        #[cfg(feature = "log")]
+        {
+            log::info!(
+                "wrote a swap space header for {} pages of {} bytes at offset {}, with the UUID {:?} and label {:?}",
+                summary.pages,
+                summary.page_size,
+                offset,
+                summary.uuid,
+                summary.label
+            );
+            for warning in &summary.warnings {
+                log::warn!("{}", warning);
+            }
+        }
+
+        Ok(summary)
+    }
+
+    #[cfg(feature = "std")]
+    /// Write the whole swap space out in order, without seeking, such as to
+    /// a pipe into a compressor, an upload or a tape.
+    ///
+    /// The size must be specified, or [`Error::SizeRequired`] is returned.
+    /// The first page is written out in full, with zeroed bootbits, followed
+    /// by zeros up to the size. The output is flushed before returning.
+    ///
+    /// Returns a summary of the swap space which was written.
+    pub fn write_stream<W: Write>(self, mut out: W) -> Result<WriteSummary, Error> {
+        let page_size = self.resolve_page_size()?;
+        let total_size_bytes = match self.size {
+            Some(Size::Bytes(size)) => size,
+            Some(Size::Pages(pages)) => u64::from(pages) * page_size,
+            None => return Err(Error::SizeRequired),
+        };
+        let version = self.version;
+        let (page, summary) = self.build_header()?;
+
+        write_region(&mut out, version, &page, (0, page_size), 0)?;
+        let mut zeros = std::io::repeat(0).take(total_size_bytes - page_size);
+        std::io::copy(&mut zeros, &mut out).map_err(Error::Allocate)?;
+        out.flush().map_err(Error::Allocate)?;
+
+        Ok(summary)
+    }
+
+    #[cfg(feature = "std")]
+    /// Resolve the size and layout of the swap space, and build its first
+    /// page, given the size detected from the handle being written to.
+    fn prepare(
+        &self,
+        page_size: u64,
+        detected: std::io::Result<u64>,
+    ) -> Result<(Layout, Header), Error> {
         let total_size_bytes = match self.size {
-            Some(size) => size,
-            None => detect_size_bytes(&mut handle).map_err(Error::SizeDetection)?,
+            Some(size) => {
+                let total_size_bytes = match size {
+                    Size::Bytes(size) => size,
+                    Size::Pages(pages) => u64::from(pages) * page_size,
+                };
+                // Handles which can't seek to their end, or report a zero size
+                // like character devices, can't be checked.
+                match detected {
+                    Ok(detected) if !self.force && detected != 0 && total_size_bytes > detected => {
+                        return Err(Error::SizeExceedsTarget(detected));
+                    }
+                    _ => total_size_bytes,
+                }
+            }
+            None => detected.map_err(Error::SizeDetection)?,
         };
+        let layout = self.layout(page_size, total_size_bytes)?;
+
+        let header = self.header(&layout)?;
+
+        Ok((layout, header))
+    }
 
-        let pages: u32 = (total_size_bytes / page_size)
-            .try_into()
-            .unwrap_or(u32::MAX);
-        if pages < MINIMUM_PAGES {
-            return Err(Error::TooFewPages(pages));
+    #[cfg(feature = "std")]
+    fn report(&mut self, phase: Phase, done: u64, total: u64) -> Result<(), Error> {
+        if let Some(progress) = &mut self.progress {
+            progress.report(phase, done, total);
         }
+        match &self.should_continue {
+            Some(should_continue) if !should_continue() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
 
-        handle
-            .seek(SeekFrom::Start(1024))
-            .map_err(Error::WriteHeader)?;
-        handle
-            .write(&[0x01, 0x00, 0x00, 0x00])
-            .map_err(Error::WriteHeader)?; // version
-        handle
-            .write(&(pages - 1).to_ne_bytes())
-            .map_err(Error::WriteHeader)?; // last page
-        handle
-            .write(&[0x00, 0x00, 0x00, 0x00])
-            .map_err(Error::WriteHeader)?; // number of bad pages
+    #[cfg(feature = "std")]
+    fn add_bad_pages(mut self, found: Vec<u32>) -> (Self, u32) {
+        let count = found.len() as u32;
+        let mut bad_pages = std::mem::take(&mut self.bad_pages);
+        bad_pages.extend(found);
 
-        handle.write(uuid.as_bytes()).map_err(Error::WriteHeader)?; // sws_uuid
-        handle.write(label.as_bytes()).map_err(Error::WriteHeader)?; // sws_volume
+        (self.bad_pages(bad_pages), count)
+    }
 
-        handle
-            .seek(SeekFrom::Start(page_size - 10))
-            .map_err(Error::WriteHeader)?;
-        handle.write(b"SWAPSPACE2").map_err(Error::WriteHeader)?; // magic
-        handle
-            .seek(SeekFrom::Start(0))
-            .map_err(Error::WriteHeader)?;
+    fn minimum_pages(&self) -> u32 {
+        if self.force {
+            FORCED_MINIMUM_PAGES
+        } else {
+            MINIMUM_PAGES
+        }
+    }
 
-        Ok(total_size_bytes)
+    fn count_pages(&self, total_size_bytes: u64, page_size: u64) -> Result<u32, Error> {
+        let pages = total_size_bytes / page_size;
+        let pages: u32 = match (pages.try_into(), self.clamp) {
+            (Ok(pages), _) => pages,
+            (Err(_), ClampPolicy::ClampWithWarning) => u32::MAX,
+            (Err(_), ClampPolicy::Error) => return Err(Error::SizeTooLarge(pages)),
+        };
+        let minimum = self.minimum_pages();
+        if pages < minimum {
+            return Err(Error::TooFewPages(pages, u64::from(minimum) * page_size));
+        }
+
+        Ok(pages)
+    }
+
+    fn resolve_page_size(&self) -> Result<u64, Error> {
+        match self.page_size {
+            Some(page_size) => check_page_size(page_size),
+            None => check_page_size(runtime_page_size()?),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    /// Confine the handle to the swap space, from the offset. The size of
+    /// the whole handle is found by seeking to its end, unless it's given.
+    fn slice<T: Seek>(
+        &self,
+        handle: T,
+        page_size: u64,
+        end: Option<u64>,
+    ) -> Result<Slice<T>, Error> {
+        let len = match self.size {
+            Some(Size::Bytes(size)) => Some(size),
+            Some(Size::Pages(pages)) => Some(u64::from(pages) * page_size),
+            None => None,
+        };
+        match end {
+            Some(end) => Slice::with_end(handle, self.offset, len, end),
+            None => Slice::new(handle, self.offset, len),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn resolve_size_bytes<T: Seek>(&self, handle: T, page_size: u64) -> Result<u64, Error> {
+        match self.size {
+            Some(Size::Bytes(size)) => Ok(size),
+            Some(Size::Pages(pages)) => Ok(u64::from(pages) * page_size),
+            None => detect_size_bytes(handle).map_err(Error::SizeDetection),
+        }
     }
 }
 
 /// General errors that can occur while configuring and writing a swap space.
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// Your page size can't fit in to a u64.
     #[error("Your page size can't fit in to a u64")]
-    GiganticPageSize(std::num::TryFromIntError),
+    GiganticPageSize(#[source] core::num::TryFromIntError),
+
+    /// The page size isn't a power of two between 2048 bytes and 1 GiB. The
+    /// attached u64 is the page size that was attempted.
+    #[error("The page size {0} isn't a power of two between 2048 bytes and 1 GiB")]
+    InvalidPageSize(u64),
 
     /// An unspecified IO error occured while trying to detect the size of the swap space.
+    #[cfg(feature = "std")]
     #[error("An unspecified IO error occured while trying to detect the size of the swap space")]
-    SizeDetection(std::io::Error),
+    SizeDetection(#[source] std::io::Error),
 
-    /// The specified label is too long: it must be at most MAXIMUM_LABEL_BYTES bytes long.
-    #[error("The specified label is too long: it must be at most MAXIMUM_LABEL_BYTES bytes long")]
+    /// The specified UUID isn't `clear`, `random`, `time` or a UUID.
+    #[error("The specified UUID {0:?} isn't clear, random, time or a UUID")]
+    InvalidUuid(String),
+
+    /// No UUID was specified, and without the `uuid` feature, none can be
+    /// generated.
+    #[error("No UUID was specified, and without the uuid feature, none can be generated")]
+    UuidRequired,
+
+    /// The specified label is too long: it must be at most MAXIMUM_LABEL_BYTES bytes long,
+    /// or one fewer when [`SwapWriter::label_terminator`] is set.
+    #[error("The specified label is too long: it must be at most 16 bytes long, or 15 with a terminator")]
     LabelTooLong,
 
+    /// The specified label contains a NUL byte.
+    #[error("The specified label contains a NUL byte")]
+    LabelContainsNul,
+
+    /// The specified label isn't valid UTF-8.
+    #[error("The specified label isn't valid UTF-8")]
+    LabelNotUtf8,
+
     /// The swap area must be at least MINIMUM_PAGES large. The attached u32 is the
-    /// number of pages that were attempted.
-    #[error("The swap area must be at least MINIMUM_PAGES large. The attached u32 is the number of pages that were attempted")]
-    TooFewPages(u32),
+    /// number of pages that were attempted, and the u64 the minimum size in bytes.
+    #[error("The swap area has {0} pages, but must be at least {1} bytes large")]
+    TooFewPages(u32, u64),
+
+    /// A bad page is outside of the swap area, or is the header page. The
+    /// attached u32 is the offending page number.
+    #[error("The bad page {0} is outside of the swap area, or is the header page")]
+    InvalidBadPage(u32),
+
+    /// There are more bad pages than fit in the header: see max_badpages. The
+    /// attached usize is the number of bad pages that were attempted.
+    #[error("There are {0} bad pages, more than fit in the header: see max_badpages")]
+    TooManyBadPages(usize),
+
+    /// A line of badblocks output isn't a block number, or the block is
+    /// beyond the addressable pages. The attached String is the offending line.
+    #[error("The line {0:?} of badblocks output isn't a block number, or the block is beyond the addressable pages")]
+    InvalidBadBlock(String),
+
+    /// The badblocks block size is zero.
+    #[error("The badblocks block size is zero")]
+    InvalidBlockSize,
+
+    /// The swap area has more pages than its format can describe. The attached
+    /// u32 is the number of pages that were attempted.
+    #[error("The swap area has {0} pages, more than its format can describe")]
+    TooManyPages(u32),
+
+    /// The swap area has more pages than the header's u32 page count can
+    /// describe: see [`ClampPolicy`]. The attached u64 is the number of pages
+    /// that were attempted.
+    #[error("The swap area has {0} pages, more than the header's u32 page count can describe")]
+    SizeTooLarge(u64),
+
+    /// The swap area is larger than the kernel of the target architecture can
+    /// use: see [`max_swap_bytes`]. The attached u64 is the largest usable size
+    /// in bytes.
+    #[error("The swap area is larger than the kernel of the target architecture can use: at most {0} bytes")]
+    ExceedsArchLimit(u64),
+
+    /// The disk's sector size isn't 512, 1024, 2048 or 4096 bytes. The
+    /// attached u64 is the sector size that was attempted.
+    #[error("The disk's sector size {0} isn't 512, 1024, 2048 or 4096 bytes")]
+    InvalidSectorSize(u64),
+
+    /// An error occurred while finding the physical offset of the swap file
+    /// with the FIEMAP or FIBMAP ioctls.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while finding the physical offset of the swap file with the FIEMAP or FIBMAP ioctls")]
+    ResumeOffset(#[source] std::io::Error),
+
+    /// The swap file's first block has no usable physical location: it may be
+    /// unallocated, inline or encoded.
+    #[error("The swap file's first block has no usable physical location: it may be unallocated, inline or encoded")]
+    NoPhysicalOffset,
+
+    /// An error occurred while writing the resume device to /sys/power.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while writing the resume device to /sys/power")]
+    SysPower(#[source] std::io::Error),
+
+    /// There is no MBR partition table: the boot signature is missing.
+    #[error("There is no MBR partition table: the boot signature is missing")]
+    MissingPartitionTable,
+
+    /// An MBR has four primary partitions. The attached usize is the
+    /// partition index that was attempted.
+    #[error("An MBR has four primary partitions, so there is no partition index {0}")]
+    InvalidPartitionIndex(usize),
+
+    /// The partition's LBA range is empty, or its offset or size in bytes
+    /// overflows a u64.
+    #[error("The partition's LBA range is empty, or its offset or size in bytes overflows a u64")]
+    InvalidLbaRange,
+
+    /// An offset was specified for an operation on a whole file or device.
+    #[error("An offset was specified for an operation on a whole file or device")]
+    OffsetUnsupported,
+
+    /// A label or UUID was specified, but the V0 format can't store them.
+    #[error("A label or UUID was specified, but the V0 format can't store them")]
+    IdentityUnsupported,
+
+    /// The swap space holds a hibernation image which hasn't been resumed.
+    /// The attached HibernationSignature is the signature found.
+    #[cfg(feature = "std")]
+    #[error("The swap space holds a {0:?} hibernation image which hasn't been resumed")]
+    HibernationImageLive(HibernationSignature),
+
+    /// The size couldn't be parsed. The attached String is the size that was
+    /// attempted.
+    #[error("The size {0:?} couldn't be parsed")]
+    InvalidSize(String),
+
+    /// An error occurred while reading the system's memory size from /proc/meminfo.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while reading the system's memory size from /proc/meminfo")]
+    MemInfo(#[source] std::io::Error),
+
+    /// The swap priority is above MAXIMUM_PRIORITY. The attached u16 is the
+    /// priority that was attempted.
+    #[error("The swap priority {0} is above MAXIMUM_PRIORITY")]
+    InvalidPriority(u16),
+
+    /// A crypttab field is empty or contains whitespace, or the mapping name
+    /// contains a slash. The attached String is the field.
+    #[error("The crypttab field {0:?} is empty or contains whitespace, or the mapping name contains a slash")]
+    InvalidCrypttabField(String),
+
+    /// An error occurred while configuring a zram device through sysfs.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while configuring a zram device through sysfs")]
+    Zram(#[source] std::io::Error),
+
+    /// An error occurred while reading or writing zswap's parameters in
+    /// /sys/module/zswap/parameters.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while reading or writing zswap's parameters in /sys/module/zswap/parameters")]
+    Zswap(#[source] std::io::Error),
+
+    /// The zswap pool percentage is above 100. The attached u8 is the
+    /// percentage that was attempted.
+    #[error("The zswap pool percentage {0} is above 100")]
+    InvalidPoolPercent(u8),
+
+    /// An error occurred while reading a random key for an encrypted swap
+    /// space from /dev/urandom.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while reading a random key for an encrypted swap space from /dev/urandom")]
+    RandomKey(#[source] std::io::Error),
+
+    /// libcryptsetup failed to set up the dm-crypt mapping. The attached
+    /// LibcryptErr is the error it reported.
+    #[cfg(all(feature = "crypt", target_os = "linux"))]
+    #[error("libcryptsetup failed to set up the dm-crypt mapping. The attached LibcryptErr is the error it reported")]
+    Crypt(#[source] libcryptsetup_rs::LibcryptErr),
+
+    /// The caller doesn't have permission to change the system's swap
+    /// spaces, or the maximum number of swap spaces are already active.
+    #[cfg(feature = "std")]
+    #[error("The caller doesn't have permission to change the system's swap spaces, or the maximum number of swap spaces are already active")]
+    PermissionDenied(#[source] std::io::Error),
+
+    /// The swap space is already active.
+    #[error("The swap space is already active")]
+    SwapActive,
+
+    /// The kernel rejected the swap space: it may not have a valid header
+    /// for this system's page size, or it isn't an active swap space.
+    #[error("The kernel rejected the swap space: it may not have a valid header for this system's page size, or it isn't an active swap space")]
+    InvalidSwapArea,
+
+    /// There isn't enough memory to move the swap space's pages back into
+    /// memory while deactivating it.
+    #[error("There isn't enough memory to move the swap space's pages back into memory while deactivating it")]
+    InsufficientMemory,
+
+    /// An unspecified error occurred while making a system call.
+    #[cfg(feature = "std")]
+    #[error("An unspecified error occurred while making a system call")]
+    Syscall(#[source] std::io::Error),
+
+    /// The operation needs the size of the swap space to be specified.
+    #[error("The operation needs the size of the swap space to be specified")]
+    SizeRequired,
+
+    /// An error occurred while opening the swap device.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while opening the swap device")]
+    OpenDevice(#[source] std::io::Error),
+
+    /// The swap device is in use: it may be mounted, or held by another user
+    /// like a device-mapper target.
+    #[error("The swap device is in use: it may be mounted, or held by another user like a device-mapper target")]
+    DeviceBusy,
+
+    /// The swap device is locked by another process.
+    #[error("The swap device is locked by another process")]
+    DeviceLocked,
+
+    /// An error occurred while locking the swap device.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while locking the swap device")]
+    Lock(#[source] std::io::Error),
+
+    /// An error occurred while creating the swap file.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while creating the swap file")]
+    CreateFile(#[source] std::io::Error),
+
+    /// The swap file's filesystem requires copy-on-write to be disabled for
+    /// swap files, but an error occurred while disabling it.
+    #[cfg(feature = "std")]
+    #[error("The swap file's filesystem requires copy-on-write to be disabled for swap files, but an error occurred while disabling it")]
+    NoCow(#[source] std::io::Error),
+
+    /// The swap file is sparse, which the kernel refuses. The attached u64 is
+    /// the offset of the first hole.
+    #[error("The swap file is sparse, which the kernel refuses: the first hole is at offset {0}")]
+    SparseFile(u64),
+
+    /// An error occurred while allocating space for the swap file.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while allocating space for the swap file")]
+    Allocate(#[source] std::io::Error),
+
+    /// Found an existing signature, which formatting would destroy. The
+    /// attached Signature is the first signature found.
+    #[cfg(feature = "std")]
+    #[error("Found an existing {} signature, which formatting would destroy", .0.name())]
+    SignatureFound(Signature),
+
+    /// Found a partition table in the bootbits: the target is probably a
+    /// whole disk. The attached PartitionTable is the kind found.
+    #[cfg(feature = "std")]
+    #[error("Found a {} partition table in the bootbits: the target is probably a whole disk", .0.name())]
+    PartitionTableFound(PartitionTable),
+
+    /// The swap device is a whole disk, with the attached partitions.
+    #[error("The swap device is a whole disk, with the partitions {}", .0.join(", "))]
+    HasPartitions(Vec<String>),
+
+    /// The attached I/O error occurred while reading sysfs
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while reading sysfs")]
+    Sysfs(#[source] std::io::Error),
+
+    /// The swap device is mounted at the attached path.
+    #[cfg(feature = "std")]
+    #[error("The swap device is mounted at {}", .0.display())]
+    Mounted(std::path::PathBuf),
+
+    /// The attached I/O error occurred while reading /proc/self/mountinfo
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while reading /proc/self/mountinfo")]
+    MountInfo(#[source] std::io::Error),
+
+    /// The attached I/O error occurred while discarding the device
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while discarding the device")]
+    Discard(#[source] std::io::Error),
+
+    /// The attached I/O error occurred while erasing the swap space
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while erasing the swap space")]
+    Erase(#[source] std::io::Error),
+
+    /// The attached I/O error occurred while writing or reading a backup
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while writing or reading a backup")]
+    Backup(#[source] std::io::Error),
+
+    /// The backup is truncated, or wasn't written by backup.
+    #[error("The backup is truncated, or wasn't written by backup")]
+    InvalidBackup,
+
+    /// A restored region didn't read back as it was written. The attached
+    /// offset is the start of the region.
+    #[error("The restored region at offset {0} didn't read back as it was written")]
+    RestoreMismatch(u64),
+
+    /// The specified size is larger than the handle being written to. The
+    /// attached size is the handle's size in bytes.
+    #[error("The specified size is larger than the handle being written to, of {0} bytes")]
+    SizeExceedsTarget(u64),
+
+    /// The attached I/O error occurred while reading /proc/swaps
+    #[cfg(feature = "std")]
+    #[error("The attached I/O error occurred while reading /proc/swaps")]
+    ProcSwaps(#[source] std::io::Error),
+
+    /// The operation was cancelled by [`SwapWriter::should_continue`], or the
+    /// `should_continue` given to [`erase`].
+    #[error("The operation was cancelled")]
+    Cancelled,
+
+    /// An error occurred while seeking through the swap area to scan for bad pages.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while seeking through the swap area to scan for bad pages")]
+    Scan(#[source] std::io::Error),
+
+    /// An error occurred while writing a field of the swap space header. The
+    /// attached offset is where the failed write started in the handle.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while writing the {} field of the swap space header at offset {offset}", field.name())]
+    WriteField {
+        /// The field being written.
+        field: HeaderField,
+        /// Where the failed write started in the handle.
+        offset: u64,
+        /// The error writing it.
+        source: std::io::Error,
+    },
 
     /// An error occurred while writing the swap space header to the area.
+    #[cfg(feature = "std")]
     #[error("An error occurred while writing the swap space header to the area")]
-    WriteHeader(std::io::Error),
+    WriteHeader(#[source] std::io::Error),
+
+    /// An error occurred while reading the swap space header from the area.
+    #[cfg(feature = "std")]
+    #[error("An error occurred while reading the swap space header from the area")]
+    ReadHeader(#[source] std::io::Error),
+
+    /// The area does not contain a swap signature at the expected offset.
+    #[error("The area does not contain a swap signature at the expected offset")]
+    MissingSignature,
+
+    /// The swap space header has a version other than 1. The attached u32 is
+    /// the version found.
+    #[error("The swap space header has the version {0}, rather than 1")]
+    UnsupportedVersion(u32),
+}
+
+impl Error {
+    /// A stable code for the kind of error, like `device_busy` or
+    /// `signature_found`, to branch on instead of the message.
+    ///
+    /// Codes are never changed or reused once released. New variants get new
+    /// codes, which is why the enum is `#[non_exhaustive]`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::GiganticPageSize(..) => "gigantic_page_size",
+            Error::InvalidPageSize(..) => "invalid_page_size",
+            #[cfg(feature = "std")]
+            Error::SizeDetection(..) => "size_detection",
+            Error::InvalidUuid(..) => "invalid_uuid",
+            Error::UuidRequired => "uuid_required",
+            Error::LabelTooLong => "label_too_long",
+            Error::LabelContainsNul => "label_contains_nul",
+            Error::LabelNotUtf8 => "label_not_utf8",
+            Error::TooFewPages(..) => "too_few_pages",
+            Error::InvalidBadPage(..) => "invalid_bad_page",
+            Error::TooManyBadPages(..) => "too_many_bad_pages",
+            Error::InvalidBadBlock(..) => "invalid_bad_block",
+            Error::InvalidBlockSize => "invalid_block_size",
+            Error::TooManyPages(..) => "too_many_pages",
+            Error::SizeTooLarge(..) => "size_too_large",
+            Error::ExceedsArchLimit(..) => "exceeds_arch_limit",
+            Error::InvalidSectorSize(..) => "invalid_sector_size",
+            #[cfg(feature = "std")]
+            Error::ResumeOffset(..) => "resume_offset",
+            Error::NoPhysicalOffset => "no_physical_offset",
+            #[cfg(feature = "std")]
+            Error::SysPower(..) => "sys_power",
+            Error::MissingPartitionTable => "missing_partition_table",
+            Error::InvalidPartitionIndex(..) => "invalid_partition_index",
+            Error::InvalidLbaRange => "invalid_lba_range",
+            Error::OffsetUnsupported => "offset_unsupported",
+            Error::IdentityUnsupported => "identity_unsupported",
+            #[cfg(feature = "std")]
+            Error::HibernationImageLive(..) => "hibernation_image_live",
+            Error::InvalidSize(..) => "invalid_size",
+            #[cfg(feature = "std")]
+            Error::MemInfo(..) => "mem_info",
+            Error::InvalidPriority(..) => "invalid_priority",
+            Error::InvalidCrypttabField(..) => "invalid_crypttab_field",
+            #[cfg(feature = "std")]
+            Error::Zram(..) => "zram",
+            #[cfg(feature = "std")]
+            Error::Zswap(..) => "zswap",
+            Error::InvalidPoolPercent(..) => "invalid_pool_percent",
+            #[cfg(feature = "std")]
+            Error::RandomKey(..) => "random_key",
+            #[cfg(all(feature = "crypt", target_os = "linux"))]
+            Error::Crypt(..) => "crypt",
+            #[cfg(feature = "std")]
+            Error::PermissionDenied(..) => "permission_denied",
+            Error::SwapActive => "swap_active",
+            Error::InvalidSwapArea => "invalid_swap_area",
+            Error::InsufficientMemory => "insufficient_memory",
+            #[cfg(feature = "std")]
+            Error::Syscall(..) => "syscall",
+            Error::SizeRequired => "size_required",
+            #[cfg(feature = "std")]
+            Error::OpenDevice(..) => "open_device",
+            Error::DeviceBusy => "device_busy",
+            Error::DeviceLocked => "device_locked",
+            #[cfg(feature = "std")]
+            Error::Lock(..) => "lock",
+            #[cfg(feature = "std")]
+            Error::CreateFile(..) => "create_file",
+            #[cfg(feature = "std")]
+            Error::NoCow(..) => "no_cow",
+            Error::SparseFile(..) => "sparse_file",
+            #[cfg(feature = "std")]
+            Error::Allocate(..) => "allocate",
+            #[cfg(feature = "std")]
+            Error::SignatureFound(..) => "signature_found",
+            #[cfg(feature = "std")]
+            Error::PartitionTableFound(..) => "partition_table_found",
+            Error::HasPartitions(..) => "has_partitions",
+            #[cfg(feature = "std")]
+            Error::Sysfs(..) => "sysfs",
+            #[cfg(feature = "std")]
+            Error::Mounted(..) => "mounted",
+            #[cfg(feature = "std")]
+            Error::MountInfo(..) => "mount_info",
+            #[cfg(feature = "std")]
+            Error::Discard(..) => "discard",
+            #[cfg(feature = "std")]
+            Error::Erase(..) => "erase",
+            #[cfg(feature = "std")]
+            Error::Backup(..) => "backup",
+            Error::InvalidBackup => "invalid_backup",
+            Error::RestoreMismatch(..) => "restore_mismatch",
+            Error::SizeExceedsTarget(..) => "size_exceeds_target",
+            #[cfg(feature = "std")]
+            Error::ProcSwaps(..) => "proc_swaps",
+            Error::Cancelled => "cancelled",
+            #[cfg(feature = "std")]
+            Error::Scan(..) => "scan",
+            #[cfg(feature = "std")]
+            Error::WriteField { .. } => "write_field",
+            #[cfg(feature = "std")]
+            Error::WriteHeader(..) => "write_header",
+            #[cfg(feature = "std")]
+            Error::ReadHeader(..) => "read_header",
+            Error::MissingSignature => "missing_signature",
+            Error::UnsupportedVersion(..) => "unsupported_version",
+        }
+    }
+}
+
+/// Check the page size is a power of two, and leaves room in the first page
+/// for the header and magic.
+pub(crate) fn check_page_size(page_size: u64) -> Result<u64, Error> {
+    if !page_size.is_power_of_two() || !(MINIMUM_PAGE_SIZE..=MAXIMUM_PAGE_SIZE).contains(&page_size)
+    {
+        return Err(Error::InvalidPageSize(page_size));
+    }
+
+    Ok(page_size)
+}
+
+#[cfg(all(feature = "host-page-size", not(target_family = "wasm")))]
+fn runtime_page_size() -> Result<u64, Error> {
+    page_size::get().try_into().map_err(Error::GiganticPageSize)
 }
 
-fn detect_size_bytes<T: WriteSeek>(mut handle: T) -> Result<u64, std::io::Error> {
+#[cfg(not(all(feature = "host-page-size", not(target_family = "wasm"))))]
+fn runtime_page_size() -> Result<u64, Error> {
+    Ok(DEFAULT_PAGE_SIZE)
+}
+
+/// The maximum number of bad pages which fit in the header, for a given page size.
+///
+/// This matches the kernel's MAX_SWAP_BADPAGES: the list runs from the end
+/// of the info struct up to the magic.
+pub fn max_badpages(page_size: u64) -> u32 {
+    (page_size.saturating_sub(BADPAGES_OFFSET + 10) / 4)
+        .try_into()
+        .unwrap_or(u32::MAX)
+}
+
+/// Drop the NUL padding at the end of a label, and refuse NULs within it.
+fn trim_label(label: &[u8]) -> Result<&[u8], Error> {
+    let len = label
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |last| last + 1);
+    if label[..len].contains(&0) {
+        return Err(Error::LabelContainsNul);
+    }
+
+    Ok(&label[..len])
+}
+
+#[cfg(feature = "std")]
+fn detect_size_bytes<T: Seek>(mut handle: T) -> Result<u64, std::io::Error> {
     handle.seek(SeekFrom::End(0))?;
     let size: u64 = handle.stream_position()?;
     handle.seek(SeekFrom::Start(0))?;
+    #[cfg(feature = "tracing")]
+    tracing::debug!(size, "detected the size by seeking to the end");
+    #[cfg(feature = "log")]
+    log::debug!("detected a size of {} bytes by seeking to the end", size);
 
     Ok(size)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
-    use hex_slice::AsHex;
-    use std::io::{Cursor, Read};
-    use std::process::Command;
-    use tempfile::NamedTempFile;
+    use std::io::Cursor;
 
+    // WASI can't run other programs.
+    #[cfg(not(target_os = "wasi"))]
     #[test]
     fn mkswap_compare() -> Result<(), std::io::Error> {
+        use hex_slice::AsHex;
+        use std::io::Read;
+        use std::process::Command;
+        use tempfile::NamedTempFile;
+
         let cmdout = NamedTempFile::new()?;
         cmdout.as_file().set_len(40 * 1024)?;
 
         println!(
             "{:#?}",
             Command::new("mkswap")
-                .args(&["--label", "🔀"])
-                .args(&["--uuid", "87705c6e-9673-4283-b33a-b87dbf6ec490"])
-                .args(&["--pagesize", "4096"])
+                .args(["--label", "🔀"])
+                .args(["--uuid", "87705c6e-9673-4283-b33a-b87dbf6ec490"])
+                .args(["--pagesize", "4096"])
                 .arg(cmdout.path())
                 .arg("40")
                 .output()
@@ -235,4 +1586,599 @@ mod test {
 
         Ok(())
     }
+    #[test]
+    fn explicit_size() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 80 * 1024]);
+        let size = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 1024)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(size.usable_bytes, 40 * 1024);
+
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[1028..1032], &9u32.to_ne_bytes());
+        assert_eq!(&bytes[4086..4096], b"SWAPSPACE2");
+
+        let writer = || SwapWriter::new().page_size(4096).size(80 * 1024);
+        assert!(matches!(
+            writer().write(Cursor::new(vec![0; 40 * 1024])),
+            Err(Error::SizeExceedsTarget(size)) if size == 40 * 1024
+        ));
+        writer()
+            .force(true)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+    }
+    #[test]
+    fn clamp() {
+        let pages = u64::from(u32::MAX) + 5;
+        let writer = || {
+            SwapWriter::new()
+                .page_size(4096)
+                .size(pages * 4096)
+                .force(true)
+        };
+        assert!(matches!(
+            writer().write(Cursor::new(vec![0; 40 * 1024])),
+            Err(Error::SizeTooLarge(attempted)) if attempted == pages
+        ));
+
+        let mut buffer = Cursor::new(vec![0; 40 * 1024]);
+        let summary = writer()
+            .clamp(ClampPolicy::ClampWithWarning)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(summary.pages, u32::MAX);
+        assert_eq!(summary.usable_bytes, u64::from(u32::MAX) * 4096);
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::Clamped {
+                pages: u32::MAX,
+                fit: pages
+            }]
+        );
+        assert_eq!(
+            &buffer.into_inner()[1028..1032],
+            &(u32::MAX - 1).to_ne_bytes()
+        );
+    }
+    #[test]
+    fn arch_limit() {
+        let size = max_swap_bytes(4096, Arch::Arm) + 4096;
+        let writer = |policy| {
+            SwapWriter::new()
+                .page_size(4096)
+                .size(size)
+                .force(true)
+                .arch_limit(Arch::Arm, policy)
+        };
+        assert!(matches!(
+            writer(ArchLimitPolicy::Error).write(Cursor::new(vec![0; 40 * 1024])),
+            Err(Error::ExceedsArchLimit(max)) if max == size - 4096
+        ));
+
+        let summary = writer(ArchLimitPolicy::Warn)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::ExceedsArchLimit {
+                arch: Arch::Arm,
+                max: size - 4096,
+                size
+            }]
+        );
+        let summary = SwapWriter::new()
+            .page_size(4096)
+            .arch_limit(Arch::Arm, ArchLimitPolicy::Error)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+        assert!(summary.warnings.is_empty());
+    }
+    #[test]
+    fn stream() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let writer = || {
+            SwapWriter::new()
+                .label("🔀".into())
+                .unwrap()
+                .uuid(uuid)
+                .page_size(4096)
+        };
+        let mut seeked = Cursor::new(vec![0; 40 * 1024 + 100]);
+        writer().write(&mut seeked).unwrap();
+
+        let mut streamed = Vec::new();
+        let summary = writer()
+            .size(40 * 1024 + 100)
+            .write_stream(&mut streamed)
+            .unwrap();
+        assert_eq!(summary.pages, 10);
+        assert_eq!(streamed, seeked.into_inner());
+
+        assert!(matches!(
+            writer().write_stream(Vec::new()),
+            Err(Error::SizeRequired)
+        ));
+    }
+    #[test]
+    fn offset() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut alone = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .uuid(uuid)
+            .page_size(4096)
+            .write(&mut alone)
+            .unwrap();
+
+        let mut image = Cursor::new(vec![0xaa; 100 * 1024]);
+        let summary = SwapWriter::new()
+            .uuid(uuid)
+            .page_size(4096)
+            .offset(20 * 1024)
+            .size(40 * 1024)
+            .zero_first_page(true)
+            .write(&mut image)
+            .unwrap();
+        assert_eq!(summary.pages, 10);
+        let bytes = image.into_inner();
+        assert!(bytes[..21 * 1024].iter().all(|&b| b == 0xaa));
+        assert_eq!(&bytes[21 * 1024..24 * 1024], &alone.get_ref()[1024..4096]);
+        assert!(bytes[24 * 1024..].iter().all(|&b| b == 0xaa));
+
+        let summary = SwapWriter::new()
+            .page_size(4096)
+            .offset(60 * 1024)
+            .write(Cursor::new(vec![0; 100 * 1024]))
+            .unwrap();
+        assert_eq!(summary.pages, 10);
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .offset(80 * 1024)
+                .size(40 * 1024)
+                .write(Cursor::new(vec![0; 100 * 1024])),
+            Err(Error::SizeExceedsTarget(size)) if size == 20 * 1024
+        ));
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .offset(100 * 1024)
+                .write(Cursor::new(vec![0; 100 * 1024])),
+            Err(Error::SizeExceedsTarget(size)) if size == 100 * 1024
+        ));
+    }
+    #[test]
+    fn size_in_pages() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 80 * 1024]);
+        let size = SwapWriter::new()
+            .page_size(4096)
+            .size_in_pages(12)
+            .unwrap()
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(size.usable_bytes, 12 * 4096);
+        assert_eq!(&buffer.into_inner()[1028..1032], &11u32.to_ne_bytes());
+
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .size_in_pages(MINIMUM_PAGES - 1),
+            Err(Error::TooFewPages(9, 40960))
+        ));
+    }
+    #[test]
+    fn invalid_page_size() {
+        for page_size in [0, 512, 3000, 2 * 1024 * 1024 * 1024] {
+            assert!(matches!(
+                SwapWriter::new()
+                    .page_size(page_size)
+                    .write(Cursor::new(vec![0; 40 * 1024])),
+                Err(Error::InvalidPageSize(attempted)) if attempted == page_size
+            ));
+        }
+        SwapWriter::new()
+            .page_size(2048)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+    }
+    #[cfg(all(feature = "host-page-size", not(target_family = "wasm")))]
+    #[test]
+    fn foreign_page_size() {
+        let host = runtime_page_size().unwrap();
+        let summary = SwapWriter::new()
+            .page_size(host)
+            .write(Cursor::new(vec![0; 16 * 65536]))
+            .unwrap();
+        assert!(summary.warnings.is_empty());
+
+        let foreign = if host == 4096 { 8192 } else { 4096 };
+        let summary = SwapWriter::new()
+            .page_size(foreign)
+            .write(Cursor::new(vec![0; 16 * 65536]))
+            .unwrap();
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::PageSizeMismatch {
+                page_size: foreign,
+                host
+            }]
+        );
+    }
+    #[test]
+    fn forced_minimum() {
+        let writer = || SwapWriter::new().page_size(4096);
+        assert!(matches!(
+            writer().write(Cursor::new(vec![0; 2 * 4096])),
+            Err(Error::TooFewPages(2, 40960))
+        ));
+
+        let summary = writer()
+            .force(true)
+            .write(Cursor::new(vec![0; 2 * 4096]))
+            .unwrap();
+        assert_eq!(summary.pages, 2);
+        writer().force(true).size_in_pages(2).unwrap();
+        assert!(matches!(
+            writer().force(true).write(Cursor::new(vec![0; 4096])),
+            Err(Error::TooFewPages(1, 8192))
+        ));
+    }
+    #[test]
+    fn bad_pages() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        let size = SwapWriter::new()
+            .page_size(4096)
+            .bad_pages(vec![7, 3, 7])
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(size.usable_bytes, 8 * 4096);
+
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[1032..1036], &2u32.to_ne_bytes());
+        assert_eq!(&bytes[1536..1540], &3u32.to_ne_bytes());
+        assert_eq!(&bytes[1540..1544], &7u32.to_ne_bytes());
+
+        for page in [0, 10] {
+            assert!(matches!(
+                SwapWriter::new()
+                    .page_size(4096)
+                    .bad_pages(vec![page])
+                    .write(Cursor::new(vec![0; 40 * 1024])),
+                Err(Error::InvalidBadPage(p)) if p == page
+            ));
+        }
+    }
+    #[test]
+    fn check_progress() {
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        SwapWriter::new()
+            .page_size(4096)
+            .progress(move |phase, done, total| recorded.lock().unwrap().push((phase, done, total)))
+            .check(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 9);
+        assert_eq!(reports[0], (Phase::Scan, 2 * 4096, 10 * 4096));
+        assert_eq!(reports[8], (Phase::Scan, 10 * 4096, 10 * 4096));
+    }
+    #[test]
+    fn cancel_check() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let checked = std::sync::Arc::new(AtomicUsize::new(0));
+        let count = checked.clone();
+        let result = SwapWriter::new()
+            .page_size(4096)
+            .should_continue(move || count.fetch_add(1, Ordering::Relaxed) + 1 < 3)
+            .check(Cursor::new(vec![0; 40 * 1024]));
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(checked.load(Ordering::Relaxed), 3);
+    }
+    #[test]
+    fn sync() {
+        struct Syncs(Cursor<Vec<u8>>, usize);
+        impl Write for Syncs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl SyncAll for Syncs {
+            fn sync_all(&mut self) -> std::io::Result<()> {
+                self.1 += 1;
+                Ok(())
+            }
+        }
+        impl Seek for Syncs {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let mut handle = Syncs(Cursor::new(vec![0; 40 * 1024]), 0);
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut handle)
+            .unwrap();
+        assert_eq!(handle.1, 0);
+        SwapWriter::new()
+            .page_size(4096)
+            .sync(true)
+            .write(&mut handle)
+            .unwrap();
+        assert_eq!(handle.1, 1);
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.set_len(40 * 1024).unwrap();
+        SwapWriter::new()
+            .page_size(4096)
+            .sync(true)
+            .write(&mut file)
+            .unwrap();
+        SwapWriter::new()
+            .page_size(4096)
+            .sync(true)
+            .write(std::io::BufWriter::new(&file))
+            .unwrap();
+    }
+    #[test]
+    fn short_writes() {
+        /// Writes at most 3 bytes per call, like a pipe or an interrupted syscall.
+        struct ShortWrites(Cursor<Vec<u8>>);
+        impl Write for ShortWrites {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(&buf[..buf.len().min(3)])
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl SyncAll for ShortWrites {}
+        impl Seek for ShortWrites {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let writer = || {
+            SwapWriter::new()
+                .page_size(4096)
+                .uuid(Uuid::nil())
+                .label("short writes".into())
+                .unwrap()
+                .bad_pages(vec![2, 5])
+        };
+        let mut expected = Cursor::new(vec![0; 40 * 1024]);
+        writer().write(&mut expected).unwrap();
+        let mut short = ShortWrites(Cursor::new(vec![0; 40 * 1024]));
+        writer().write(&mut short).unwrap();
+        assert_eq!(short.0.into_inner(), expected.into_inner());
+    }
+    #[test]
+    fn too_many_bad_pages() {
+        assert_eq!(max_badpages(4096), 637);
+
+        let bad_pages: Vec<u32> = (1..=638).collect();
+        assert!(matches!(
+            SwapWriter::new()
+                .page_size(4096)
+                .bad_pages(bad_pages)
+                .write(Cursor::new(vec![0; 4096 * 1024])),
+            Err(Error::TooManyBadPages(638))
+        ));
+    }
+    #[test]
+    fn error_messages() {
+        use std::error::Error as _;
+
+        let error = SwapWriter::new()
+            .page_size(1000)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The page size 1000 isn't a power of two between 2048 bytes and 1 GiB"
+        );
+        assert!(error.source().is_none());
+        assert_eq!(error.code(), "invalid_page_size");
+        assert_eq!(Error::DeviceBusy.code(), "device_busy");
+        assert_eq!(
+            Error::TooFewPages(1, 4096).code(),
+            Error::TooFewPages(5, 2048).code()
+        );
+
+        let error = Error::WriteHeader(std::io::ErrorKind::WriteZero.into());
+        let source = error.source().unwrap();
+        assert_eq!(source.to_string(), "write zero");
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(error);
+        assert!(boxed.source().is_some());
+    }
+
+    #[test]
+    fn write_field_errors() {
+        /// Fails every write from `fail_at` onwards.
+        struct Failing {
+            inner: Cursor<Vec<u8>>,
+            fail_at: u64,
+        }
+        impl Write for Failing {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let room = self.fail_at.saturating_sub(self.inner.position());
+                if room == 0 {
+                    return Err(std::io::ErrorKind::Other.into());
+                }
+                self.inner.write(&buf[..buf.len().min(room as usize)])
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        impl SyncAll for Failing {}
+        impl Seek for Failing {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+        let write = |offset: u64, fail_at: u64| {
+            let handle = Failing {
+                inner: Cursor::new(vec![0; 80 * 1024]),
+                fail_at,
+            };
+            match SwapWriter::new()
+                .page_size(4096)
+                .offset(offset)
+                .write(handle)
+            {
+                Err(Error::WriteField { field, offset, .. }) => (field, offset),
+                other => panic!("unexpected {:?}", other),
+            }
+        };
+
+        assert_eq!(write(0, 1024 + 6), (HeaderField::LastPage, 1024 + 6));
+        assert_eq!(write(0, 1024 + 20), (HeaderField::Uuid, 1024 + 20));
+        assert_eq!(write(0, 1024 + 28), (HeaderField::Label, 1024 + 28));
+        assert_eq!(write(0, 4096 - 10), (HeaderField::Magic, 4096 - 10));
+        assert_eq!(
+            write(40 * 1024, 40 * 1024 + 1024),
+            (HeaderField::Version, 40 * 1024 + 1024)
+        );
+        let error = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 1024)
+            .write_stream(Failing {
+                inner: Cursor::new(Vec::new()),
+                fail_at: 0,
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "An error occurred while writing the bootbits field of the swap space header at offset 0"
+        );
+    }
+
+    #[test]
+    fn uuid_v5() {
+        let write = || {
+            let mut buffer = Cursor::new(vec![0; 40 * 1024]);
+            let summary = SwapWriter::new()
+                .page_size(4096)
+                .uuid_v5(&Uuid::NAMESPACE_URL, b"https://example.com/image")
+                .write(&mut buffer)
+                .unwrap();
+            (summary.uuid.unwrap(), buffer.into_inner())
+        };
+        let (uuid, bytes) = write();
+        assert_eq!(uuid.get_version_num(), 5);
+        assert_eq!(write(), (uuid, bytes));
+
+        let other = SwapWriter::new()
+            .page_size(4096)
+            .uuid_v5(&Uuid::NAMESPACE_URL, b"https://example.com/other")
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+        assert_ne!(other.uuid, Some(uuid));
+    }
+    #[test]
+    fn big_endian() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .endianness(Endianness::Big)
+            .bad_pages(vec![3])
+            .write(&mut buffer)
+            .unwrap();
+
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[1024..1036], &[0, 0, 0, 1, 0, 0, 0, 9, 0, 0, 0, 1]);
+        assert_eq!(&bytes[1536..1540], &[0, 0, 0, 3]);
+    }
+    #[test]
+    fn bootbits() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0xff; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        assert!(buffer.get_ref()[..1024].iter().all(|&b| b == 0xff));
+
+        SwapWriter::new()
+            .page_size(4096)
+            .bootbits(BootBits::Zero)
+            .write(&mut buffer)
+            .unwrap();
+        assert!(buffer.get_ref()[..1024].iter().all(|&b| b == 0));
+    }
+    #[test]
+    fn zero_first_page() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0xff; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .label("short".into())
+            .unwrap()
+            .zero_first_page(true)
+            .write(&mut buffer)
+            .unwrap();
+
+        let bytes = buffer.into_inner();
+        assert!(bytes[..1024].iter().all(|&b| b == 0xff));
+        assert_eq!(&bytes[1052..1068], b"short\0\0\0\0\0\0\0\0\0\0\0");
+        assert!(bytes[1068..4086].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[4086..4096], b"SWAPSPACE2");
+        assert_eq!(bytes[4096], 0xff);
+    }
+    #[test]
+    fn version_0() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0xff; 40 * 1024]);
+        let size = SwapWriter::new()
+            .page_size(4096)
+            .version(SwapVersion::V0)
+            .bad_pages(vec![3])
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(size.usable_bytes, 9 * 4096);
+
+        let bytes = buffer.into_inner();
+        assert_eq!(&bytes[0..3], &[0b11110110, 0b00000011, 0]);
+        assert!(bytes[3..4086].iter().all(|&b| b == 0));
+        assert_eq!(&bytes[4086..4096], b"SWAP-SPACE");
+
+        assert!(matches!(
+            SwapWriter::new()
+                .label("swap".into())
+                .unwrap()
+                .version(SwapVersion::V0)
+                .write(Cursor::new(vec![0; 40 * 1024])),
+            Err(Error::IdentityUnsupported)
+        ));
+    }
+    #[test]
+    fn from_existing() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut old: Cursor<Vec<u8>> = Cursor::new(vec![0; 160 * 1024]);
+        SwapWriter::new()
+            .label("swap".into())
+            .unwrap()
+            .uuid(uuid)
+            .page_size(16384)
+            .write(&mut old)
+            .unwrap();
+
+        let mut new: Cursor<Vec<u8>> = Cursor::new(vec![0; 320 * 1024]);
+        SwapWriter::from_existing(&mut old)
+            .unwrap()
+            .write(&mut new)
+            .unwrap();
+
+        let header = SwapReader::new().page_size(16384).read(&mut new).unwrap();
+        assert_eq!(header.uuid, uuid);
+        assert_eq!(header.label, "swap");
+        assert_eq!(header.last_page, 19);
+    }
 }
@@ -0,0 +1,205 @@
+//! Format swap spaces into the partitions of a whole-disk image.
+
+use std::io::{SeekFrom, Write};
+
+use uuid::Uuid;
+
+use crate::{Error, ReadSeek, SwapWriter};
+#[cfg(feature = "gpt")]
+use crate::{SyncAll, Warning, WriteSeek, WriteSummary};
+
+/// The GPT partition type GUID of Linux swap partitions.
+///
+/// systemd-gpt-auto-generator activates partitions of this type on the boot
+/// disk, as described by the Discoverable Partitions Specification.
+pub const GPT_SWAP_TYPE: Uuid = Uuid::from_u128(0x0657fd6d_a4ab_43c4_84e5_0933c84b4f4f);
+
+/// The MBR partition type of Linux swap partitions.
+pub const MBR_SWAP_TYPE: u8 = 0x82;
+
+/// The offset of the MBR's four partition entries.
+const MBR_ENTRIES_OFFSET: u64 = 446;
+
+/// Read the type of one of the four primary partitions of an MBR partition
+/// table.
+pub fn mbr_partition_type<T: ReadSeek>(mut disk: T, index: usize) -> Result<u8, Error> {
+    let offset = mbr_entry_offset(&mut disk, index)?;
+    let mut partition_type = [0; 1];
+    disk.seek(SeekFrom::Start(offset + 4))
+        .map_err(Error::ReadHeader)?;
+    disk.read_exact(&mut partition_type)
+        .map_err(Error::ReadHeader)?;
+    disk.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    Ok(partition_type[0])
+}
+
+/// Set the type of one of the four primary partitions of an MBR partition
+/// table, like [`MBR_SWAP_TYPE`] for a partition being formatted as swap.
+pub fn set_mbr_partition_type<T: ReadSeek + Write>(
+    mut disk: T,
+    index: usize,
+    partition_type: u8,
+) -> Result<(), Error> {
+    let offset = mbr_entry_offset(&mut disk, index)?;
+    disk.seek(SeekFrom::Start(offset + 4))
+        .map_err(Error::WriteHeader)?;
+    disk.write_all(&[partition_type])
+        .map_err(Error::WriteHeader)?;
+    disk.flush().map_err(Error::WriteHeader)?;
+    disk.seek(SeekFrom::Start(0)).map_err(Error::WriteHeader)?;
+
+    Ok(())
+}
+
+/// Find the offset of an MBR partition entry, checking there is an MBR.
+fn mbr_entry_offset<T: ReadSeek>(mut disk: T, index: usize) -> Result<u64, Error> {
+    if index >= 4 {
+        return Err(Error::InvalidPartitionIndex(index));
+    }
+    let mut boot_signature = [0; 2];
+    disk.seek(SeekFrom::Start(510)).map_err(Error::ReadHeader)?;
+    match disk.read_exact(&mut boot_signature) {
+        Ok(()) if boot_signature == [0x55, 0xaa] => {}
+        Ok(()) => return Err(Error::MissingPartitionTable),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(Error::MissingPartitionTable)
+        }
+        Err(e) => return Err(Error::ReadHeader(e)),
+    }
+
+    Ok(MBR_ENTRIES_OFFSET + 16 * index as u64)
+}
+
+impl SwapWriter {
+    /// Place the swap space in a partition, from `first_lba` to `last_lba`
+    /// inclusive, in sectors of `sector_size` bytes
+    ///
+    /// This sets [`SwapWriter::offset`] and [`SwapWriter::size`], so the
+    /// swap space can be written with [`SwapWriter::write`] to the handle of
+    /// the whole disk.
+    pub fn lba_range(self, first_lba: u64, last_lba: u64, sector_size: u64) -> Result<Self, Error> {
+        let sectors = last_lba
+            .checked_sub(first_lba)
+            .and_then(|sectors| sectors.checked_add(1))
+            .ok_or(Error::InvalidLbaRange)?;
+        let offset = first_lba
+            .checked_mul(sector_size)
+            .ok_or(Error::InvalidLbaRange)?;
+        let size = sectors
+            .checked_mul(sector_size)
+            .ok_or(Error::InvalidLbaRange)?;
+
+        Ok(self.offset(offset).size(size))
+    }
+
+    /// Write the swap space into a partition of the gpt crate, on the
+    /// handle of the whole disk
+    ///
+    /// See [`SwapWriter::lba_range`]. A partition whose type isn't
+    /// [`GPT_SWAP_TYPE`] is written with a warning in
+    /// [`WriteSummary::warnings`], as it won't be discovered as swap: set its
+    /// `part_type_guid` to `gpt::partition_types::LINUX_SWAP`.
+    #[cfg(feature = "gpt")]
+    pub fn write_partition<T: WriteSeek + SyncAll>(
+        self,
+        disk: T,
+        partition: &gpt::partition::Partition,
+        block_size: gpt::disk::LogicalBlockSize,
+    ) -> Result<WriteSummary, Error> {
+        let mut summary = self
+            .lba_range(partition.first_lba, partition.last_lba, block_size.into())?
+            .write(disk)?;
+        if partition.part_type_guid.guid != GPT_SWAP_TYPE {
+            summary
+                .warnings
+                .push(Warning::PartitionTypeNotSwap(partition.part_type_guid.guid));
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn lba_range() {
+        let mut disk = Cursor::new(vec![0; 2 * 1024 * 1024]);
+        let summary = SwapWriter::new()
+            .page_size(4096)
+            .lba_range(2048, 2048 + 79, 512)
+            .unwrap()
+            .write(&mut disk)
+            .unwrap();
+        assert_eq!(summary.pages, 10);
+        assert_eq!(
+            &disk.get_ref()[2048 * 512 + 4086..2048 * 512 + 4096],
+            b"SWAPSPACE2"
+        );
+
+        for (first, last) in [(10, 9), (u64::MAX / 2, u64::MAX)] {
+            assert!(matches!(
+                SwapWriter::new().lba_range(first, last, 512),
+                Err(Error::InvalidLbaRange)
+            ));
+        }
+    }
+
+    #[test]
+    fn mbr_type() {
+        let mut disk = Cursor::new(vec![0; 1024]);
+        assert!(matches!(
+            mbr_partition_type(&mut disk, 0),
+            Err(Error::MissingPartitionTable)
+        ));
+
+        disk.get_mut()[510..512].copy_from_slice(&[0x55, 0xaa]);
+        disk.get_mut()[446 + 16 + 4] = 0x83;
+        assert_eq!(mbr_partition_type(&mut disk, 1).unwrap(), 0x83);
+        set_mbr_partition_type(&mut disk, 1, MBR_SWAP_TYPE).unwrap();
+        assert_eq!(mbr_partition_type(&mut disk, 1).unwrap(), MBR_SWAP_TYPE);
+        assert_eq!(mbr_partition_type(&mut disk, 0).unwrap(), 0);
+        assert!(matches!(
+            set_mbr_partition_type(&mut disk, 4, MBR_SWAP_TYPE),
+            Err(Error::InvalidPartitionIndex(4))
+        ));
+    }
+
+    #[cfg(feature = "gpt")]
+    #[test]
+    fn gpt_swap_type() {
+        assert_eq!(gpt::partition_types::LINUX_SWAP.guid, GPT_SWAP_TYPE);
+    }
+
+    #[cfg(feature = "gpt")]
+    #[test]
+    fn gpt_partition() {
+        let mut partition = gpt::partition::Partition::zero();
+        partition.part_type_guid = gpt::partition_types::LINUX_SWAP;
+        partition.first_lba = 34;
+        partition.last_lba = 34 + 9;
+
+        let mut disk = Cursor::new(vec![0; 1024 * 1024]);
+        let summary = SwapWriter::new()
+            .page_size(4096)
+            .write_partition(&mut disk, &partition, gpt::disk::LogicalBlockSize::Lb4096)
+            .unwrap();
+        assert!(summary.warnings.is_empty());
+        assert_eq!(&disk.get_ref()[35 * 4096 - 10..35 * 4096], b"SWAPSPACE2");
+
+        partition.part_type_guid = gpt::partition_types::LINUX_FS;
+        let summary = SwapWriter::new()
+            .page_size(4096)
+            .write_partition(&mut disk, &partition, gpt::disk::LogicalBlockSize::Lb4096)
+            .unwrap();
+        assert_eq!(
+            summary.warnings,
+            vec![Warning::PartitionTypeNotSwap(
+                gpt::partition_types::LINUX_FS.guid
+            )]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Cheaply detect whether a device looks like a swap space.
+
+use std::io::{ErrorKind, SeekFrom};
+
+use crate::{Error, ReadSeek, SwapVersion, SWAPSPACE2_MAGIC, SWAP_SPACE_MAGIC};
+
+/// The page sizes probed for a swap signature, smallest first. These start
+/// at the smallest page size a swap space can be written with.
+pub(crate) const PROBE_PAGE_SIZES: [u64; 6] = [2048, 4096, 8192, 16384, 32768, 65536];
+
+/// Check whether the handle contains a swap signature.
+///
+/// Only the magic at the end of the first page is read, for each of the
+/// common page sizes. The header itself is not parsed: use
+/// [`SwapReader`](crate::SwapReader) for that.
+pub fn probe<T: ReadSeek>(handle: T) -> Result<Option<SwapVersion>, Error> {
+    let found = find_magic(handle, |magic| match magic {
+        SWAPSPACE2_MAGIC => Some(SwapVersion::V1),
+        SWAP_SPACE_MAGIC => Some(SwapVersion::V0),
+        _ => None,
+    })?;
+
+    Ok(found.map(|(version, _)| version))
+}
+
+/// Read the magic at the end of the first page for each of the common page
+/// sizes, returning the first one `recognize` accepts along with its page size.
+pub(crate) fn find_magic<T: ReadSeek, R>(
+    mut handle: T,
+    recognize: impl Fn(&[u8; 10]) -> Option<R>,
+) -> Result<Option<(R, u64)>, Error> {
+    let mut magic = [0; 10];
+    let mut found = None;
+
+    for page_size in PROBE_PAGE_SIZES {
+        handle
+            .seek(SeekFrom::Start(page_size - 10))
+            .map_err(Error::ReadHeader)?;
+        match handle.read_exact(&mut magic) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(Error::ReadHeader(e)),
+        }
+
+        if let Some(recognized) = recognize(&magic) {
+            found = Some((recognized, page_size));
+            break;
+        }
+    }
+
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn probe_page_sizes() {
+        assert_eq!(probe(Cursor::new(vec![0; 128 * 1024])).unwrap(), None);
+        assert_eq!(probe(Cursor::new(vec![0; 100])).unwrap(), None);
+
+        for page_size in PROBE_PAGE_SIZES {
+            let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 10 * 65536]);
+            SwapWriter::new()
+                .page_size(page_size)
+                .write(&mut buffer)
+                .unwrap();
+            assert_eq!(probe(&mut buffer).unwrap(), Some(SwapVersion::V1));
+        }
+    }
+}
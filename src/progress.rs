@@ -0,0 +1,33 @@
+//! Report the progress of long-running operations.
+
+/// A long-running operation reported to a [`Progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Scanning for bad pages, with [`SwapWriter::check`](crate::SwapWriter::check)
+    /// or [`SwapWriter::check_read_write`](crate::SwapWriter::check_read_write).
+    Scan,
+    /// Erasing the swap space, with [`erase`](crate::erase).
+    Erase,
+    /// Discarding the device.
+    Discard,
+    /// Allocating a new swap file by writing zeros.
+    Allocate,
+    /// Filling the holes of a sparse swap file with zeros.
+    FillHoles,
+}
+
+/// Receives progress reports from long-running operations, such as to
+/// drive a progress bar or log.
+///
+/// This is implemented for closures taking the same arguments.
+pub trait Progress {
+    /// Report that `done` bytes out of `total` have been processed in the
+    /// current phase.
+    fn report(&mut self, phase: Phase, done: u64, total: u64);
+}
+
+impl<F: FnMut(Phase, u64, u64)> Progress for F {
+    fn report(&mut self, phase: Phase, done: u64, total: u64) {
+        self(phase, done, total)
+    }
+}
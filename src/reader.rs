@@ -0,0 +1,275 @@
+//! Parse the header of an existing swap space.
+
+use std::io::SeekFrom;
+use uuid::Uuid;
+
+use crate::probe::find_magic;
+use crate::{
+    check_page_size, Endianness, Error, ReadSeek, SwapVersion, BOOTBITS_BYTES, MAXIMUM_LABEL_BYTES,
+    SWAPSPACE2_MAGIC, SWAP_SPACE_MAGIC,
+};
+
+/// The fields of a swap space header, as read from an existing area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapHeader {
+    /// The format of the swap space.
+    pub version: SwapVersion,
+    /// The page size the swap space was created with, as specified or
+    /// inferred from the position of its magic.
+    pub page_size: u64,
+    /// The index of the last usable page.
+    pub last_page: u32,
+    /// The number of entries in the bad pages list.
+    ///
+    /// For V0 swap spaces, the number of unusable pages before the last page.
+    pub nr_badpages: u32,
+    /// The swap space's UUID. Always nil for V0 swap spaces.
+    pub uuid: Uuid,
+    /// The swap space's label, up to the first NUL byte, replacing bytes
+    /// which aren't UTF-8 with U+FFFD, for display. Always empty for V0 swap
+    /// spaces.
+    pub label: String,
+    /// The label field exactly as read, including its NUL padding and
+    /// anything after the first NUL. Always zeros for V0 swap spaces.
+    pub label_bytes: [u8; MAXIMUM_LABEL_BYTES],
+}
+
+impl SwapHeader {
+    /// The raw bytes of the label, up to the first NUL byte, as blkid reads
+    /// it. These can be given to [`SwapWriter::label_bytes`](crate::SwapWriter::label_bytes)
+    /// to preserve the label exactly, even if it isn't UTF-8.
+    pub fn raw_label(&self) -> &[u8] {
+        let len = self
+            .label_bytes
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(MAXIMUM_LABEL_BYTES);
+        &self.label_bytes[..len]
+    }
+}
+
+/// A builder to read the header of an existing swap space.
+///
+/// None of these fields are mandatory: they can all be detected.
+pub struct SwapReader {
+    page_size: Option<u64>,
+    endianness: Endianness,
+}
+
+impl SwapReader {
+    /// Construct a new SwapReader with all-default Nones
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            page_size: None,
+            endianness: Endianness::Native,
+        }
+    }
+
+    /// Specify the page size the swap space was created with
+    ///
+    /// Must be a power of two within the bounds the writer accepts, or
+    /// [`SwapReader::read`] returns [`Error::InvalidPageSize`].
+    pub fn page_size(mut self, page_size: u64) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Specify the byte order the header fields were written with
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Read and parse the swap space header from a device.
+    ///
+    /// If no page size was specified, it is inferred by looking for the magic
+    /// at the end of the first page for each of the common page sizes, like
+    /// [`probe`](crate::probe).
+    pub fn read<T: ReadSeek>(self, mut handle: T) -> Result<SwapHeader, Error> {
+        let page_size = match self.page_size {
+            Some(page_size) => check_page_size(page_size)?,
+            None => {
+                find_magic(&mut handle, |magic| {
+                    [SWAPSPACE2_MAGIC, SWAP_SPACE_MAGIC]
+                        .contains(&magic)
+                        .then_some(())
+                })?
+                .ok_or(Error::MissingSignature)?
+                .1
+            }
+        };
+
+        let mut magic = [0; 10];
+        handle
+            .seek(SeekFrom::Start(page_size - 10))
+            .map_err(Error::ReadHeader)?;
+        handle.read_exact(&mut magic).map_err(Error::ReadHeader)?;
+        match &magic {
+            SWAPSPACE2_MAGIC => {}
+            SWAP_SPACE_MAGIC => return read_v0(handle, page_size),
+            _ => return Err(Error::MissingSignature),
+        }
+
+        let mut info = [0; 44];
+        handle
+            .seek(SeekFrom::Start(BOOTBITS_BYTES))
+            .map_err(Error::ReadHeader)?;
+        handle.read_exact(&mut info).map_err(Error::ReadHeader)?;
+        handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+        let field = |offset: usize| {
+            self.endianness
+                .u32_from_bytes(info[offset..offset + 4].try_into().unwrap())
+        };
+        let version = field(0);
+        if version != 1 {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let mut header = SwapHeader {
+            version: SwapVersion::V1,
+            page_size,
+            last_page: field(4),
+            nr_badpages: field(8),
+            uuid: Uuid::from_slice(&info[12..28]).unwrap(),
+            label: String::new(),
+            label_bytes: info[28..44].try_into().unwrap(),
+        };
+        header.label = String::from_utf8_lossy(header.raw_label()).into_owned();
+
+        Ok(header)
+    }
+}
+
+fn read_v0<T: ReadSeek>(mut handle: T, page_size: u64) -> Result<SwapHeader, Error> {
+    let mut bitmap = vec![0; (page_size - 10) as usize];
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+    handle.read_exact(&mut bitmap).map_err(Error::ReadHeader)?;
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    let usable = |page: usize| bitmap[page / 8] & (1 << (page % 8)) != 0;
+    let last_page = (0..bitmap.len() * 8)
+        .rev()
+        .find(|&page| usable(page))
+        .ok_or(Error::MissingSignature)?;
+    let nr_badpages = (1..last_page).filter(|&page| !usable(page)).count();
+
+    Ok(SwapHeader {
+        version: SwapVersion::V0,
+        page_size,
+        last_page: last_page as u32,
+        nr_badpages: nr_badpages as u32,
+        uuid: Uuid::nil(),
+        label: String::new(),
+        label_bytes: [0; MAXIMUM_LABEL_BYTES],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_back() {
+        let uuid = Uuid::parse_str("87705c6e-9673-4283-b33a-b87dbf6ec490").unwrap();
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .label("🔀".into())
+            .unwrap()
+            .uuid(uuid)
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(
+            header,
+            SwapHeader {
+                version: SwapVersion::V1,
+                page_size: 4096,
+                last_page: 9,
+                nr_badpages: 0,
+                uuid,
+                label: "🔀".into(),
+                label_bytes: *b"\xf0\x9f\x94\x80\0\0\0\0\0\0\0\0\0\0\0\0",
+            }
+        );
+
+        // A label which isn't UTF-8, with garbage after its terminator.
+        buffer.get_mut()[1024 + 28..1024 + 44].copy_from_slice(b"caf\xe9\0garbage\0\0\0\0");
+        let header = SwapReader::new().page_size(4096).read(&mut buffer).unwrap();
+        assert_eq!(header.label, "caf\u{fffd}");
+        assert_eq!(header.raw_label(), b"caf\xe9");
+        assert_eq!(&header.label_bytes[5..12], b"garbage");
+        let writer = SwapWriter::from_existing(&mut buffer).unwrap();
+        let (page, _) = writer.size(40 * 1024).build_header().unwrap();
+        assert_eq!(&page[1024 + 28..1024 + 33], b"caf\xe9\0");
+
+        assert!(matches!(
+            SwapReader::new()
+                .page_size(4096)
+                .read(Cursor::new(vec![0; 40 * 1024])),
+            Err(Error::MissingSignature)
+        ));
+        for page_size in [0, 1000, 1 << 40] {
+            assert!(matches!(
+                SwapReader::new().page_size(page_size).read(&mut buffer),
+                Err(Error::InvalidPageSize(size)) if size == page_size
+            ));
+        }
+    }
+    #[test]
+    fn infer_page_size() {
+        for page_size in [2048, 4096, 16384, 65536] {
+            let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 10 * 65536]);
+            SwapWriter::new()
+                .page_size(page_size)
+                .write(&mut buffer)
+                .unwrap();
+            assert_eq!(
+                SwapReader::new().read(&mut buffer).unwrap().page_size,
+                page_size
+            );
+        }
+
+        assert!(matches!(
+            SwapReader::new().read(Cursor::new(vec![0; 10 * 65536])),
+            Err(Error::MissingSignature)
+        ));
+    }
+    #[test]
+    fn detect_version() {
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .version(SwapVersion::V0)
+            .bad_pages(vec![4])
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            SwapReader::new().page_size(4096).read(&mut buffer).unwrap(),
+            SwapHeader {
+                version: SwapVersion::V0,
+                page_size: 4096,
+                last_page: 9,
+                nr_badpages: 1,
+                uuid: Uuid::nil(),
+                label: String::new(),
+                label_bytes: [0; MAXIMUM_LABEL_BYTES],
+            }
+        );
+
+        let mut buffer: Cursor<Vec<u8>> = Cursor::new(vec![0; 40 * 1024]);
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        buffer.get_mut()[1024] = 2;
+        assert!(matches!(
+            SwapReader::new().page_size(4096).read(&mut buffer),
+            Err(Error::UnsupportedVersion(2))
+        ));
+    }
+}
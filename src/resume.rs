@@ -0,0 +1,172 @@
+//! Find where hibernation resumes from in a swap file.
+
+use std::fs::File;
+#[cfg(feature = "hibernate")]
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+
+use crate::{runtime_page_size, Error};
+
+const FS_IOC_FIEMAP: libc::Ioctl = libc::_IOWR::<FiemapHeader>(b'f' as u32, 11);
+const FIBMAP: libc::Ioctl = libc::_IO(0, 1);
+const FIGETBSZ: libc::Ioctl = libc::_IO(0, 2);
+/// Sync the file before mapping it, so delayed allocations have an extent.
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+/// Extent flags which mean the physical offset can't be used to resume.
+const FIEMAP_EXTENT_UNUSABLE: u32 = 0x2 | 0x4 | 0x8 | 0x100 | 0x200;
+
+/// The header of struct fiemap, from linux/fiemap.h.
+#[repr(C)]
+#[derive(Default)]
+struct FiemapHeader {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+}
+
+/// struct fiemap_extent, from linux/fiemap.h.
+#[repr(C)]
+#[derive(Default)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+/// struct fiemap with room for a single extent.
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+    header: FiemapHeader,
+    extents: [FiemapExtent; 1],
+}
+
+/// Find the `resume_offset` of a swap file, for hibernating to it.
+///
+/// This is the physical location of the file's first block on its
+/// filesystem's device, in units of the running system's page size, which
+/// the kernel expects. It is found with the FIEMAP ioctl, falling back to
+/// FIBMAP, which needs CAP_SYS_RAWIO, for filesystems without FIEMAP.
+pub fn resume_offset(file: &File) -> Result<u64, Error> {
+    let page_size = runtime_page_size()?;
+    let physical = match fiemap_first_extent(file) {
+        Ok(physical) => physical,
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOTTY)) => {
+            fibmap_first_block(file)?
+        }
+        Err(e) => return Err(Error::ResumeOffset(e)),
+    };
+
+    physical
+        .map(|physical| physical / page_size)
+        .ok_or(Error::NoPhysicalOffset)
+}
+
+/// Point the running kernel's hibernation at a swap space, by writing its
+/// device and offset to `/sys/power/resume` and `/sys/power/resume_offset`.
+///
+/// A block device is resumed from directly. For a swap file, the device is
+/// the one holding its filesystem, and the offset is its
+/// [`resume_offset`]. This needs root, and takes effect without a reboot.
+#[cfg(feature = "hibernate")]
+pub fn set_resume(swap: &File) -> Result<(), Error> {
+    let metadata = swap.metadata().map_err(Error::SizeDetection)?;
+    let (device, offset) = if metadata.file_type().is_block_device() {
+        (metadata.rdev(), 0)
+    } else {
+        (metadata.dev(), resume_offset(swap)?)
+    };
+
+    // Setting the device can start resuming from it, so the offset goes first.
+    std::fs::write("/sys/power/resume_offset", offset.to_string()).map_err(Error::SysPower)?;
+    std::fs::write("/sys/power/resume", device_number(device)).map_err(Error::SysPower)?;
+
+    Ok(())
+}
+
+/// Format a device number as `major:minor`, as sysfs expects.
+#[cfg(feature = "hibernate")]
+fn device_number(device: u64) -> String {
+    format!("{}:{}", libc::major(device), libc::minor(device))
+}
+
+/// Find the physical byte offset of the first extent with FIEMAP, or None
+/// if it has no usable location.
+fn fiemap_first_extent(file: &File) -> std::io::Result<Option<u64>> {
+    let mut fiemap = Fiemap::default();
+    fiemap.header.fm_length = u64::MAX;
+    fiemap.header.fm_flags = FIEMAP_FLAG_SYNC;
+    fiemap.header.fm_extent_count = 1;
+    // SAFETY: the struct has room for the one extent it asks for.
+    if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP, &mut fiemap) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let extent = &fiemap.extents[0];
+    if fiemap.header.fm_mapped_extents == 0
+        || extent.fe_logical != 0
+        || extent.fe_flags & FIEMAP_EXTENT_UNUSABLE != 0
+    {
+        return Ok(None);
+    }
+    Ok(Some(extent.fe_physical))
+}
+
+/// Find the physical byte offset of the first block with FIBMAP, or None
+/// if it isn't allocated.
+fn fibmap_first_block(file: &File) -> Result<Option<u64>, Error> {
+    // FIBMAP counts in filesystem blocks, which FIGETBSZ reports the size of.
+    let mut block_size: libc::c_int = 0;
+    let mut block: libc::c_int = 0;
+    // SAFETY: each ioctl writes the one int it's given.
+    unsafe {
+        if libc::ioctl(file.as_raw_fd(), FIGETBSZ, &mut block_size) != 0
+            || libc::ioctl(file.as_raw_fd(), FIBMAP, &mut block) != 0
+        {
+            return Err(Error::ResumeOffset(std::io::Error::last_os_error()));
+        }
+    }
+
+    Ok((block > 0).then(|| block as u64 * block_size as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn fiemap_layout() {
+        assert_eq!(std::mem::size_of::<FiemapHeader>(), 32);
+        assert_eq!(std::mem::size_of::<FiemapExtent>(), 56);
+    }
+
+    #[test]
+    fn offset_of_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        // tmpfs has neither FIEMAP nor FIBMAP, like some CI sandboxes.
+        match resume_offset(&file) {
+            Err(Error::ResumeOffset(_)) => return,
+            result => assert!(matches!(result, Err(Error::NoPhysicalOffset))),
+        }
+
+        file.write_all(&[1; 64 * 1024]).unwrap();
+        match resume_offset(&file) {
+            Ok(_) | Err(Error::NoPhysicalOffset) => {}
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[cfg(feature = "hibernate")]
+    #[test]
+    fn device_numbers() {
+        assert_eq!(device_number(libc::makedev(8, 3)), "8:3");
+        assert_eq!(device_number(libc::makedev(259, 65536)), "259:65536");
+    }
+}
@@ -0,0 +1,233 @@
+//! Scan a swap space for pages which can't be used.
+
+use std::io::{SeekFrom, Write};
+
+use crate::{check_page_size, Error, ReadSeek};
+
+/// Read every page after the header, returning the pages which couldn't be read.
+pub(crate) fn read_only<T: ReadSeek>(
+    mut handle: T,
+    page_size: u64,
+    pages: u32,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<Vec<u32>, Error> {
+    let total = u64::from(pages) * page_size;
+    let mut buffer = vec![0; page_size as usize];
+    let mut bad_pages = Vec::new();
+
+    handle
+        .seek(SeekFrom::Start(page_size))
+        .map_err(Error::Scan)?;
+    for page in 1..pages {
+        if handle.read_exact(&mut buffer).is_err() {
+            bad_pages.push(page);
+            handle
+                .seek(SeekFrom::Start(u64::from(page + 1) * page_size))
+                .map_err(Error::Scan)?;
+        }
+        progress(u64::from(page + 1) * page_size, total)?;
+    }
+    handle.seek(SeekFrom::Start(0)).map_err(Error::Scan)?;
+
+    Ok(bad_pages)
+}
+
+/// Test every page after the header by reading it, writing its complement,
+/// verifying the complement and restoring the original contents. Returns the
+/// pages which failed any of those steps.
+pub(crate) fn read_write<T: ReadSeek + Write>(
+    mut handle: T,
+    page_size: u64,
+    pages: u32,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<Vec<u32>, Error> {
+    let total = u64::from(pages) * page_size;
+    let mut original = vec![0; page_size as usize];
+    let mut pattern = vec![0; page_size as usize];
+    let mut verify = vec![0; page_size as usize];
+    let mut bad_pages = Vec::new();
+
+    for page in 1..pages {
+        let start = SeekFrom::Start(u64::from(page) * page_size);
+        handle.seek(start).map_err(Error::Scan)?;
+        if handle.read_exact(&mut original).is_err() {
+            bad_pages.push(page);
+            progress(u64::from(page + 1) * page_size, total)?;
+            continue;
+        }
+
+        for (p, o) in pattern.iter_mut().zip(&original) {
+            *p = !o;
+        }
+
+        let verified = handle.seek(start).is_ok()
+            && handle.write_all(&pattern).is_ok()
+            && handle.flush().is_ok()
+            && handle.seek(start).is_ok()
+            && handle.read_exact(&mut verify).is_ok()
+            && verify == pattern;
+
+        handle.seek(start).map_err(Error::Scan)?;
+        let restored = handle.write_all(&original).is_ok() && handle.flush().is_ok();
+
+        if !(verified && restored) {
+            bad_pages.push(page);
+        }
+        progress(u64::from(page + 1) * page_size, total)?;
+    }
+    handle.seek(SeekFrom::Start(0)).map_err(Error::Scan)?;
+
+    Ok(bad_pages)
+}
+
+/// Convert the output of `badblocks(8)` into swap page numbers, suitable
+/// for [`SwapWriter::bad_pages`](crate::SwapWriter::bad_pages).
+///
+/// `block_size` is the block size badblocks was run with (its `-b` flag,
+/// 1024 by default). Each bad block marks every page it overlaps as bad,
+/// except the header page, which can't be: if it's unreadable, writing the
+/// header fails instead.
+pub fn parse_badblocks(output: &str, block_size: u64, page_size: u64) -> Result<Vec<u32>, Error> {
+    if block_size == 0 {
+        return Err(Error::InvalidBlockSize);
+    }
+    let page_size = check_page_size(page_size)?;
+    let mut bad_pages = Vec::new();
+
+    for line in output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        let invalid = || Error::InvalidBadBlock(line.to_string());
+        let block: u64 = line.parse().map_err(|_| invalid())?;
+        let start = block.checked_mul(block_size).ok_or_else(invalid)?;
+        let end = start.checked_add(block_size - 1).ok_or_else(invalid)?;
+        for page in (start / page_size).max(1)..=end / page_size {
+            bad_pages.push(u32::try_from(page).map_err(|_| invalid())?);
+        }
+    }
+    bad_pages.dedup();
+
+    Ok(bad_pages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::{Cursor, Read, Seek};
+    use std::ops::Range;
+
+    /// A handle which fails to read any byte in one range, and silently
+    /// drops writes in another.
+    struct Flaky {
+        inner: Cursor<Vec<u8>>,
+        unreadable: Range<u64>,
+        unwritable: Range<u64>,
+    }
+
+    impl Flaky {
+        fn new(inner: Vec<u8>) -> Self {
+            Self {
+                inner: Cursor::new(inner),
+                unreadable: 0..0,
+                unwritable: 0..0,
+            }
+        }
+
+        fn touches(&self, range: &Range<u64>, len: usize) -> bool {
+            let start = self.inner.position();
+            start < range.end && range.start < start + len as u64
+        }
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.touches(&self.unreadable, buf.len()) {
+                return Err(std::io::Error::other("unreadable"));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.touches(&self.unwritable, buf.len()) {
+                self.inner.seek(SeekFrom::Current(buf.len() as i64))?;
+                return Ok(buf.len());
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn finds_unreadable_pages() {
+        let mut handle = Flaky::new(vec![0; 40 * 1024]);
+        handle.unreadable = 3 * 4096 + 100..4 * 4096 + 1;
+        assert_eq!(
+            read_only(&mut handle, 4096, 10, |_, _| Ok(())).unwrap(),
+            vec![3, 4]
+        );
+        assert_eq!(handle.inner.position(), 0);
+    }
+
+    #[test]
+    fn finds_unwritable_pages() {
+        let contents: Vec<u8> = (0..40 * 1024).map(|i| i as u8).collect();
+        let mut handle = Flaky::new(contents.clone());
+        handle.unreadable = 2 * 4096..2 * 4096 + 1;
+        handle.unwritable = 5 * 4096..6 * 4096;
+        assert_eq!(
+            read_write(&mut handle, 4096, 10, |_, _| Ok(())).unwrap(),
+            vec![2, 5]
+        );
+        assert_eq!(handle.inner.get_ref(), &contents);
+    }
+    #[test]
+    fn badblocks_to_pages() {
+        assert_eq!(
+            parse_badblocks("3\n4\n\n  20\n", 1024, 4096).unwrap(),
+            vec![1, 5]
+        );
+        assert_eq!(
+            parse_badblocks("1\n", 16384, 4096).unwrap(),
+            vec![4, 5, 6, 7]
+        );
+        assert!(matches!(
+            parse_badblocks("12\nbogus\n", 1024, 4096),
+            Err(Error::InvalidBadBlock(line)) if line == "bogus"
+        ));
+        assert!(matches!(
+            parse_badblocks("18446744073709551615\n", 1024, 4096),
+            Err(Error::InvalidBadBlock(_))
+        ));
+        assert!(matches!(
+            parse_badblocks("1\n", 0, 4096),
+            Err(Error::InvalidBlockSize)
+        ));
+        assert!(matches!(
+            parse_badblocks("1\n", 1024, 0),
+            Err(Error::InvalidPageSize(0))
+        ));
+
+        // The header page is never reported, so the result can be written.
+        let bad_pages = parse_badblocks("0\n3\n5\n", 1024, 4096).unwrap();
+        assert_eq!(bad_pages, vec![1]);
+        SwapWriter::new()
+            .page_size(4096)
+            .bad_pages(bad_pages)
+            .write(Cursor::new(vec![0; 40 * 1024]))
+            .unwrap();
+    }
+}
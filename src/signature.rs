@@ -0,0 +1,360 @@
+//! Detect filesystem and other signatures which formatting would destroy.
+
+use std::io::{ErrorKind, SeekFrom, Write};
+
+use crate::hibernation::HibernationSignature;
+use crate::probe::PROBE_PAGE_SIZES;
+use crate::{Error, ReadSeek, SWAPSPACE2_MAGIC, SWAP_SPACE_MAGIC};
+
+/// A kind of signature recognized by [`detect_signatures`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signature {
+    /// An ext2, ext3 or ext4 filesystem.
+    Ext,
+    /// An XFS filesystem.
+    Xfs,
+    /// A btrfs filesystem.
+    Btrfs,
+    /// A LUKS encrypted volume.
+    Luks,
+    /// A FAT12, FAT16 or FAT32 filesystem.
+    Fat,
+    /// An NTFS filesystem.
+    Ntfs,
+    /// An ISO 9660 filesystem.
+    Iso9660,
+    /// An LVM2 physical volume.
+    Lvm2,
+    /// A Linux software RAID member, with a version 1.1 or 1.2 superblock.
+    MdRaid,
+    /// A swap space.
+    Swap,
+    /// A swap space holding a hibernation image.
+    Hibernation,
+}
+
+impl Signature {
+    /// The name blkid uses for this signature's TYPE.
+    pub fn name(self) -> &'static str {
+        match self {
+            Signature::Ext => "ext4",
+            Signature::Xfs => "xfs",
+            Signature::Btrfs => "btrfs",
+            Signature::Luks => "crypto_LUKS",
+            Signature::Fat => "vfat",
+            Signature::Ntfs => "ntfs",
+            Signature::Iso9660 => "iso9660",
+            Signature::Lvm2 => "LVM2_member",
+            Signature::MdRaid => "linux_raid_member",
+            Signature::Swap => "swap",
+            Signature::Hibernation => "swsuspend",
+        }
+    }
+}
+
+/// A signature found by [`detect_signatures`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoundSignature {
+    /// The kind of signature.
+    pub signature: Signature,
+    /// The offset of the signature's magic bytes, from the start of the handle.
+    pub offset: u64,
+    /// The length of the signature's magic bytes.
+    pub length: usize,
+}
+
+/// The signatures at fixed offsets, and their magic bytes.
+const MAGICS: [(Signature, u64, &[u8]); 11] = [
+    (Signature::Ext, 0x438, b"\x53\xef"),
+    (Signature::Xfs, 0, b"XFSB"),
+    (Signature::Btrfs, 0x10040, b"_BHRfS_M"),
+    (Signature::Luks, 0, b"LUKS\xba\xbe"),
+    (Signature::Fat, 0x36, b"FAT1"),
+    (Signature::Fat, 0x52, b"FAT32"),
+    (Signature::Ntfs, 3, b"NTFS    "),
+    (Signature::Iso9660, 0x8001, b"CD001"),
+    (Signature::Lvm2, 0x200, b"LABELONE"),
+    (Signature::MdRaid, 0, b"\xfc\x4e\x2b\xa9"),
+    (Signature::MdRaid, 0x1000, b"\xfc\x4e\x2b\xa9"),
+];
+
+/// Look for signatures of filesystems, volumes and swap spaces.
+///
+/// This is a small table of the most common signatures, and is not a
+/// replacement for blkid. Signatures out of the handle's bounds are
+/// skipped. Swap and hibernation signatures are checked for each of the
+/// common page sizes, as with [`probe`](crate::probe), and every one found
+/// is returned: a swap space reformatted with a different page size can
+/// hold several.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn detect_signatures<T: ReadSeek>(mut handle: T) -> Result<Vec<FoundSignature>, Error> {
+    let mut found = Vec::new();
+    let mut buffer = [0; 10];
+
+    let swap_magics = PROBE_PAGE_SIZES.map(|page_size| (None, page_size - 10, &[0; 10][..]));
+    let fixed_magics = MAGICS.map(|(signature, offset, magic)| (Some(signature), offset, magic));
+    for (signature, offset, magic) in fixed_magics.into_iter().chain(swap_magics) {
+        let buffer = &mut buffer[..magic.len()];
+        handle
+            .seek(SeekFrom::Start(offset))
+            .map_err(Error::ReadHeader)?;
+        match handle.read_exact(buffer) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => continue,
+            Err(e) => return Err(Error::ReadHeader(e)),
+        }
+
+        let recognized = match signature {
+            Some(signature) => (buffer == magic).then_some(signature),
+            None => recognize_swap(buffer),
+        };
+        if let Some(signature) = recognized {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(signature = signature.name(), offset, "found a signature");
+            #[cfg(feature = "log")]
+            log::debug!(
+                "found a {} signature at offset {}",
+                signature.name(),
+                offset
+            );
+            found.push(FoundSignature {
+                signature,
+                offset,
+                length: magic.len(),
+            });
+        }
+    }
+
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+
+    Ok(found)
+}
+
+/// Recognize the swap and hibernation magics at the end of a first page.
+fn recognize_swap(magic: &[u8]) -> Option<Signature> {
+    let magic: &[u8; 10] = magic.try_into().ok()?;
+    match magic {
+        SWAPSPACE2_MAGIC | SWAP_SPACE_MAGIC => Some(Signature::Swap),
+        _ => HibernationSignature::recognize(magic).map(|_| Signature::Hibernation),
+    }
+}
+
+/// A kind of partition table recognized by [`detect_partition_table`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionTable {
+    /// An MBR partition table.
+    Dos,
+    /// A GPT partition table, behind a protective MBR.
+    Gpt,
+}
+
+impl PartitionTable {
+    /// The name blkid uses for this partition table's PTTYPE.
+    pub fn name(self) -> &'static str {
+        match self {
+            PartitionTable::Dos => "dos",
+            PartitionTable::Gpt => "gpt",
+        }
+    }
+}
+
+/// Look for a partition table in the bootbits, the first 1024 bytes.
+///
+/// A partition table there means the handle is probably a whole disk,
+/// rather than the partition meant to be formatted. FAT and NTFS boot
+/// sectors share the MBR's boot signature, and aren't partition tables.
+pub fn detect_partition_table<T: ReadSeek>(mut handle: T) -> Result<Option<PartitionTable>, Error> {
+    let mut sector = [0; 512];
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+    let read = handle.read_exact(&mut sector);
+    handle.seek(SeekFrom::Start(0)).map_err(Error::ReadHeader)?;
+    match read {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(Error::ReadHeader(e)),
+    }
+
+    if sector[510..512] != [0x55, 0xaa] {
+        return Ok(None);
+    }
+    let boot_sector = MAGICS.iter().any(|&(signature, offset, magic)| {
+        matches!(signature, Signature::Fat | Signature::Ntfs)
+            && sector[offset as usize..].starts_with(magic)
+    });
+    if boot_sector {
+        return Ok(None);
+    }
+
+    // Each of the four entries starts with a boot indicator, and has the
+    // partition type at offset 4.
+    let entries: Vec<&[u8]> = sector[446..510].chunks(16).collect();
+    if entries
+        .iter()
+        .any(|entry| entry[0] != 0 && entry[0] != 0x80)
+    {
+        return Ok(None);
+    }
+    if entries.iter().any(|entry| entry[4] == 0xee) {
+        Ok(Some(PartitionTable::Gpt))
+    } else if entries.iter().any(|entry| entry[4] != 0) {
+        Ok(Some(PartitionTable::Dos))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Zero the magic bytes of every signature found by [`detect_signatures`],
+/// like `wipefs --all`.
+///
+/// Call this before writing a new swap space, so stale signatures don't
+/// confuse blkid, and through it udev and systemd-gpt-auto-generator. Only
+/// the magic bytes are zeroed, so the rest of the old data is left in place.
+///
+/// Returns the signatures which were wiped.
+pub fn wipe_signatures<T: ReadSeek + Write>(mut handle: T) -> Result<Vec<FoundSignature>, Error> {
+    let found = detect_signatures(&mut handle)?;
+
+    for signature in &found {
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            signature = signature.signature.name(),
+            offset = signature.offset,
+            "wiping a signature"
+        );
+        #[cfg(feature = "log")]
+        log::info!(
+            "wiping the {} signature at offset {}",
+            signature.signature.name(),
+            signature.offset
+        );
+        handle
+            .seek(SeekFrom::Start(signature.offset))
+            .map_err(Error::WriteHeader)?;
+        handle
+            .write_all(&vec![0; signature.length])
+            .map_err(Error::WriteHeader)?;
+    }
+    handle.flush().map_err(Error::WriteHeader)?;
+    handle
+        .seek(SeekFrom::Start(0))
+        .map_err(Error::WriteHeader)?;
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SwapWriter;
+    use std::io::Cursor;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            detect_signatures(Cursor::new(vec![0; 100])).unwrap(),
+            vec![]
+        );
+
+        let mut buffer = Cursor::new(vec![0; 128 * 1024]);
+        buffer.get_mut()[0x438..0x43a].copy_from_slice(b"\x53\xef");
+        buffer.get_mut()[0x10040..0x10048].copy_from_slice(b"_BHRfS_M");
+        assert_eq!(
+            detect_signatures(&mut buffer).unwrap(),
+            vec![
+                FoundSignature {
+                    signature: Signature::Ext,
+                    offset: 0x438,
+                    length: 2,
+                },
+                FoundSignature {
+                    signature: Signature::Btrfs,
+                    offset: 0x10040,
+                    length: 8,
+                },
+            ]
+        );
+
+        let mut buffer = Cursor::new(vec![0; 16 * 8192]);
+        SwapWriter::new()
+            .page_size(8192)
+            .write(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            detect_signatures(&mut buffer).unwrap(),
+            vec![FoundSignature {
+                signature: Signature::Swap,
+                offset: 8192 - 10,
+                length: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn wipe() {
+        let mut buffer = Cursor::new(vec![0; 16 * 8192]);
+        buffer.get_mut()[0..4].copy_from_slice(b"XFSB");
+        SwapWriter::new()
+            .page_size(4096)
+            .write(&mut buffer)
+            .unwrap();
+        SwapWriter::new()
+            .page_size(8192)
+            .write(&mut buffer)
+            .unwrap();
+
+        let wiped = wipe_signatures(&mut buffer).unwrap();
+        assert_eq!(
+            wiped
+                .iter()
+                .map(|found| found.signature)
+                .collect::<Vec<_>>(),
+            vec![Signature::Xfs, Signature::Swap, Signature::Swap]
+        );
+        assert_eq!(detect_signatures(&mut buffer).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn partition_table() {
+        let mut sector = vec![0; 1024];
+        assert_eq!(detect_partition_table(Cursor::new(&sector)).unwrap(), None);
+
+        sector[510..512].copy_from_slice(&[0x55, 0xaa]);
+        assert_eq!(detect_partition_table(Cursor::new(&sector)).unwrap(), None);
+
+        sector[446 + 4] = 0x83;
+        assert_eq!(
+            detect_partition_table(Cursor::new(&sector)).unwrap(),
+            Some(PartitionTable::Dos)
+        );
+
+        sector[462 + 4] = 0xee;
+        assert_eq!(
+            detect_partition_table(Cursor::new(&sector)).unwrap(),
+            Some(PartitionTable::Gpt)
+        );
+
+        sector[0x52..0x57].copy_from_slice(b"FAT32");
+        assert_eq!(detect_partition_table(Cursor::new(&sector)).unwrap(), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn refuse_signature() {
+        use std::io::{Seek, Write};
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[0; 16 * 4096]).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"XFSB").unwrap();
+
+        assert!(matches!(
+            SwapWriter::new().page_size(4096).write_file(&mut file),
+            Err(Error::SignatureFound(Signature::Xfs))
+        ));
+        SwapWriter::new()
+            .page_size(4096)
+            .force(true)
+            .write_file(&mut file)
+            .unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Parse human-readable sizes, and recommend swap sizes.
+
+use crate::Error;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// A rule for how large a swap space should be, relative to the system's
+/// memory, for [`recommend_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizePolicy {
+    /// A percentage of the system's memory.
+    RatioOfRam(u32),
+    /// Red Hat's recommendation: twice the memory up to 2 GiB of it, as
+    /// much as the memory up to 8 GiB, and 4 GiB beyond that.
+    RedHat,
+    /// Ubuntu's recommendation: the square root of the memory in GiB,
+    /// rounded, and at least 1 GiB.
+    Ubuntu,
+    /// Enough to hibernate to: the memory, plus Ubuntu's recommendation as
+    /// headroom for what's already swapped out.
+    Hibernation,
+}
+
+/// Recommend a swap size in bytes, by applying the policy to the system's
+/// total memory from /proc/meminfo.
+///
+/// The result can be passed to [`SwapWriter::size`](crate::SwapWriter::size)
+/// or [`create_swap_file`](crate::create_swap_file).
+pub fn recommend_size(policy: SizePolicy) -> Result<u64, Error> {
+    Ok(size_for_memory(policy, mem_total()?))
+}
+
+fn size_for_memory(policy: SizePolicy, memory: u64) -> u64 {
+    let ubuntu = || {
+        // Round to the nearest GiB before taking the root.
+        let gib = (memory + GIB / 2) / GIB;
+        gib.max(1).isqrt().max(1) * GIB
+    };
+
+    match policy {
+        SizePolicy::RatioOfRam(percent) => {
+            (u128::from(memory) * u128::from(percent) / 100).min(u128::from(u64::MAX)) as u64
+        }
+        SizePolicy::RedHat if memory <= 2 * GIB => memory * 2,
+        SizePolicy::RedHat if memory <= 8 * GIB => memory,
+        SizePolicy::RedHat => 4 * GIB,
+        SizePolicy::Ubuntu => ubuntu(),
+        SizePolicy::Hibernation => memory.saturating_add(ubuntu()),
+    }
+}
+
+/// Parse a human-readable size into bytes.
+///
+/// Accepts a number with an optional fractional part and suffix:
+///
+/// * no suffix or `B`: bytes.
+/// * `K`, `M`, `G`, `T`, `P`, `E`, optionally followed by `iB`: powers of 1024.
+/// * `KB`, `MB`, `GB`, `TB`, `PB`, `EB`: powers of 1000.
+/// * `%`: a percentage of the system's total memory, from /proc/meminfo.
+///
+/// Suffixes are case-insensitive, and whitespace between the number and
+/// suffix is allowed: `512MiB`, `8G`, `4096 K` and `50%` are all valid.
+pub fn parse_size(size: &str) -> Result<u64, Error> {
+    let invalid = || Error::InvalidSize(size.to_string());
+
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+
+    let suffix = suffix.trim_start().to_ascii_uppercase();
+    let (multiplier, divisor) = match suffix.as_str() {
+        "" | "B" => (1, 1),
+        "%" => (mem_total()?, 100),
+        _ => {
+            let (exponent, rest) = match suffix.chars().next() {
+                Some('K') => (1, &suffix[1..]),
+                Some('M') => (2, &suffix[1..]),
+                Some('G') => (3, &suffix[1..]),
+                Some('T') => (4, &suffix[1..]),
+                Some('P') => (5, &suffix[1..]),
+                Some('E') => (6, &suffix[1..]),
+                _ => return Err(invalid()),
+            };
+            let base: u64 = match rest {
+                "" | "IB" => 1024,
+                "B" => 1000,
+                _ => return Err(invalid()),
+            };
+            (base.pow(exponent), 1)
+        }
+    };
+
+    let whole: u128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| invalid())?
+    };
+    let fraction_scale = 10u128
+        .checked_pow(fraction.len() as u32)
+        .ok_or_else(invalid)?;
+    let fraction: u128 = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse().map_err(|_| invalid())?
+    };
+
+    let multiplier = u128::from(multiplier);
+    let bytes = whole
+        .checked_mul(multiplier)
+        .and_then(|bytes| {
+            let fraction = fraction.checked_mul(multiplier)? / fraction_scale;
+            bytes.checked_add(fraction)
+        })
+        .ok_or_else(invalid)?
+        / divisor;
+
+    bytes.try_into().map_err(|_| invalid())
+}
+
+/// Read the system's total memory in bytes from /proc/meminfo.
+pub(crate) fn mem_total() -> Result<u64, Error> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").map_err(Error::MemInfo)?;
+    let kib = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or_else(|| {
+            Error::MemInfo(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "no MemTotal in /proc/meminfo",
+            ))
+        })?;
+
+    Ok(kib * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(parse_size("4096K").unwrap(), 4096 * 1024);
+        assert_eq!(parse_size("512MiB").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("8G").unwrap(), 8 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("8 gb").unwrap(), 8_000_000_000);
+        assert_eq!(parse_size("1KB").unwrap(), 1000);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert_eq!(parse_size("16EiB").ok(), None);
+
+        for invalid in ["", "G", "12Q", "1.2.3", "12KiBB", "-1"] {
+            assert!(matches!(parse_size(invalid), Err(Error::InvalidSize(_))));
+        }
+        assert!(matches!(
+            parse_size("0.99999999999999999999999999999999999999E"),
+            Err(Error::InvalidSize(_))
+        ));
+    }
+
+    #[test]
+    fn recommend() {
+        assert_eq!(
+            size_for_memory(SizePolicy::RatioOfRam(50), 8 * GIB),
+            4 * GIB
+        );
+        assert_eq!(size_for_memory(SizePolicy::RedHat, GIB), 2 * GIB);
+        assert_eq!(size_for_memory(SizePolicy::RedHat, 6 * GIB), 6 * GIB);
+        assert_eq!(size_for_memory(SizePolicy::RedHat, 32 * GIB), 4 * GIB);
+        assert_eq!(size_for_memory(SizePolicy::Ubuntu, GIB / 2), GIB);
+        assert_eq!(size_for_memory(SizePolicy::Ubuntu, 16 * GIB - 1), 4 * GIB);
+        assert_eq!(size_for_memory(SizePolicy::Hibernation, 8 * GIB), 10 * GIB);
+        assert!(recommend_size(SizePolicy::Ubuntu).unwrap() >= GIB);
+    }
+}
@@ -0,0 +1,153 @@
+//! Confine reads, writes and seeks to a window of a larger handle.
+
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::{Error, SyncAll};
+
+/// The `[start, end)` window of a handle, like fscommon's StreamSlice.
+///
+/// Positions are relative to the start of the window. Reads stop at its end,
+/// and writes past it write nothing, which `write_all` reports as an error.
+pub(crate) struct Slice<T> {
+    inner: T,
+    start: u64,
+    end: u64,
+    position: u64,
+}
+
+impl<T: Seek> Slice<T> {
+    /// Confine the handle to `len` bytes from `start`, or to everything from
+    /// `start` if no length is given. The window never extends past the end
+    /// of the handle, unless it reports a zero size like character devices,
+    /// or can't seek to its end at all. A window starting at or past the end
+    /// of the handle is refused with [`Error::SizeExceedsTarget`].
+    pub(crate) fn new(mut inner: T, start: u64, len: Option<u64>) -> Result<Self, Error> {
+        let inner_end = inner.seek(SeekFrom::End(0)).unwrap_or(0);
+        Self::with_end(inner, start, len, inner_end)
+    }
+
+    /// Confine the handle like [`Slice::new`], given the size of the handle
+    /// instead of seeking to its end.
+    pub(crate) fn with_end(
+        mut inner: T,
+        start: u64,
+        len: Option<u64>,
+        inner_end: u64,
+    ) -> Result<Self, Error> {
+        if inner_end != 0 && start >= inner_end {
+            return Err(Error::SizeExceedsTarget(inner_end));
+        }
+        let end = match len {
+            Some(len) if inner_end == 0 => start.saturating_add(len),
+            Some(len) => start.saturating_add(len).min(inner_end),
+            None => inner_end,
+        };
+        inner
+            .seek(SeekFrom::Start(start))
+            .map_err(Error::SizeDetection)?;
+
+        Ok(Self {
+            inner,
+            start,
+            end: end.max(start),
+            position: 0,
+        })
+    }
+
+    /// The size of the window.
+    pub(crate) fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    fn remaining(&self, len: usize) -> usize {
+        self.len().saturating_sub(self.position).min(len as u64) as usize
+    }
+}
+
+impl<T: Read + Seek> Read for Slice<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.remaining(buf.len());
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Write + Seek> Write for Slice<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.remaining(buf.len());
+        let written = self.inner.write(&buf[..len])?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: SyncAll + Seek> SyncAll for Slice<T> {
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.inner.sync_all()
+    }
+}
+
+impl<T: Seek> Seek for Slice<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or(ErrorKind::InvalidInput)?;
+
+        self.inner.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn window() {
+        let mut inner = Cursor::new(vec![0; 100]);
+        let mut slice = Slice::new(&mut inner, 10, Some(20)).unwrap();
+        assert_eq!(slice.seek(SeekFrom::End(0)).unwrap(), 20);
+        assert!(slice.seek(SeekFrom::Current(-21)).is_err());
+
+        slice.seek(SeekFrom::Start(15)).unwrap();
+        assert_eq!(
+            slice.write_all(&[1; 10]).unwrap_err().kind(),
+            ErrorKind::WriteZero
+        );
+        let mut read = Vec::new();
+        slice.seek(SeekFrom::Start(0)).unwrap();
+        slice.read_to_end(&mut read).unwrap();
+        assert_eq!(read.len(), 20);
+
+        let bytes = inner.into_inner();
+        assert!(bytes[..25].iter().all(|&b| b == 0));
+        assert!(bytes[25..30].iter().all(|&b| b == 1));
+        assert!(bytes[30..].iter().all(|&b| b == 0));
+
+        let slice = Slice::new(Cursor::new(vec![0; 100]), 90, Some(20)).unwrap();
+        assert_eq!(slice.end, 100);
+        let slice = Slice::with_end(Cursor::new(vec![0; 100]), 90, None, 95).unwrap();
+        assert_eq!(slice.len(), 5);
+
+        for start in [100, 150] {
+            assert!(matches!(
+                Slice::new(Cursor::new(vec![0; 100]), start, None),
+                Err(Error::SizeExceedsTarget(100))
+            ));
+        }
+        assert!(matches!(
+            Slice::with_end(Cursor::new(vec![0; 100]), 95, Some(10), 95),
+            Err(Error::SizeExceedsTarget(95))
+        ));
+    }
+}
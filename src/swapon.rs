@@ -0,0 +1,143 @@
+//! Activate and deactivate swap spaces with the swapon(2) and swapoff(2)
+//! system calls.
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::config::check_priority;
+use crate::{Discard, Error};
+
+const SWAP_FLAG_PREFER: libc::c_int = 0x8000;
+const SWAP_FLAG_PRIO_MASK: libc::c_int = 0x7fff;
+const SWAP_FLAG_DISCARD: libc::c_int = 0x10000;
+const SWAP_FLAG_DISCARD_ONCE: libc::c_int = 0x20000;
+const SWAP_FLAG_DISCARD_PAGES: libc::c_int = 0x40000;
+
+/// A builder for the options passed to [`swapon`].
+///
+/// None of these fields are mandatory: the kernel has defaults for them all.
+#[derive(Debug, Clone, Default)]
+pub struct SwapOnOptions {
+    priority: Option<u16>,
+    discard: Option<Discard>,
+}
+
+impl SwapOnOptions {
+    /// Construct a new SwapOnOptions with all-default Nones
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the priority, up to [`MAXIMUM_PRIORITY`](crate::MAXIMUM_PRIORITY):
+    /// higher priority swap spaces are used first. If no priority is set, the
+    /// kernel assigns a decreasing negative priority.
+    pub fn priority(mut self, priority: u16) -> Result<Self, Error> {
+        self.priority = Some(check_priority(priority)?);
+        Ok(self)
+    }
+
+    /// Enable discarding freed pages, for SSDs and thinly provisioned storage
+    pub fn discard(mut self, discard: Discard) -> Self {
+        self.discard = Some(discard);
+        self
+    }
+
+    fn flags(&self) -> libc::c_int {
+        let mut flags = 0;
+        if let Some(priority) = self.priority {
+            flags |= SWAP_FLAG_PREFER | (libc::c_int::from(priority) & SWAP_FLAG_PRIO_MASK);
+        }
+        flags |= match self.discard {
+            None => 0,
+            Some(Discard::All) => SWAP_FLAG_DISCARD,
+            Some(Discard::Once) => SWAP_FLAG_DISCARD | SWAP_FLAG_DISCARD_ONCE,
+            Some(Discard::Pages) => SWAP_FLAG_DISCARD | SWAP_FLAG_DISCARD_PAGES,
+        };
+        flags
+    }
+}
+
+/// Activate the swap space at the path.
+///
+/// Requires CAP_SYS_ADMIN, and the swap space must have been created with
+/// the page size of the running system.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", skip_all, fields(path = %path.as_ref().display()), err)
+)]
+pub fn swapon<P: AsRef<Path>>(path: P, options: &SwapOnOptions) -> Result<(), Error> {
+    let path = path_to_cstring(path.as_ref())?;
+
+    // SAFETY: path is a valid NUL-terminated string which outlives the call.
+    if unsafe { libc::swapon(path.as_ptr(), options.flags()) } != 0 {
+        return Err(syscall_error(std::io::Error::last_os_error()));
+    }
+    #[cfg(feature = "tracing")]
+    tracing::info!("activated the swap space");
+    #[cfg(feature = "log")]
+    log::info!("activated the swap space {}", path.to_string_lossy());
+
+    Ok(())
+}
+
+/// Deactivate the swap space at the path.
+///
+/// Requires CAP_SYS_ADMIN. The kernel moves the swap space's pages back into
+/// memory first, so this can take a long time or fail if there isn't enough
+/// memory.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", skip_all, fields(path = %path.as_ref().display()), err)
+)]
+pub fn swapoff<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let path = path_to_cstring(path.as_ref())?;
+
+    // SAFETY: path is a valid NUL-terminated string which outlives the call.
+    if unsafe { libc::swapoff(path.as_ptr()) } != 0 {
+        return Err(syscall_error(std::io::Error::last_os_error()));
+    }
+    #[cfg(feature = "tracing")]
+    tracing::info!("deactivated the swap space");
+    #[cfg(feature = "log")]
+    log::info!("deactivated the swap space {}", path.to_string_lossy());
+
+    Ok(())
+}
+
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|e| Error::Syscall(e.into()))
+}
+
+/// Map the errnos swapon(2) and swapoff(2) document to typed errors.
+pub(crate) fn syscall_error(error: std::io::Error) -> Error {
+    match error.raw_os_error() {
+        Some(libc::EPERM) => Error::PermissionDenied(error),
+        Some(libc::EBUSY) => Error::SwapActive,
+        Some(libc::EINVAL) => Error::InvalidSwapArea,
+        Some(libc::ENOMEM) => Error::InsufficientMemory,
+        _ => Error::Syscall(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        assert_eq!(SwapOnOptions::new().flags(), 0);
+        assert_eq!(
+            SwapOnOptions::new()
+                .priority(5)
+                .unwrap()
+                .discard(Discard::Once)
+                .flags(),
+            0x8005 | 0x30000
+        );
+        assert!(matches!(
+            SwapOnOptions::new().priority(32768),
+            Err(Error::InvalidPriority(32768))
+        ));
+    }
+}
@@ -0,0 +1,363 @@
+//! Inspect the swap spaces, mounts and block devices of the running Linux system.
+
+use std::fs::{File, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+/// What backs an active swap space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapKind {
+    /// A block device, including zram and device-mapper devices.
+    Partition,
+    /// A swap file.
+    File,
+    /// A type this crate doesn't know about.
+    Other(String),
+}
+
+/// An active swap space, as listed in /proc/swaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapEntry {
+    /// The path the swap space was activated with.
+    pub path: PathBuf,
+    /// What backs the swap space.
+    pub kind: SwapKind,
+    /// The usable size of the swap space in bytes.
+    pub size: u64,
+    /// How many bytes of the swap space are in use.
+    pub used: u64,
+    /// The priority: higher priority swap spaces are used first.
+    pub priority: i32,
+}
+
+/// Read the active swap spaces from /proc/swaps.
+///
+/// A system without swap support has no /proc/swaps, and no active swap.
+/// Lines which don't parse are skipped.
+pub fn swaps() -> Result<Vec<SwapEntry>, Error> {
+    match std::fs::read_to_string("/proc/swaps") {
+        Ok(contents) => Ok(parse_swaps(&contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(Error::ProcSwaps(e)),
+    }
+}
+
+/// A swap file or device, which may be active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapArea {
+    path: PathBuf,
+}
+
+/// How much of an active swap space is in use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapUsage {
+    /// The usable size of the swap space in bytes.
+    pub total: u64,
+    /// How many bytes are in use.
+    pub used: u64,
+    /// How many bytes are free.
+    pub free: u64,
+    /// The priority: higher priority swap spaces are used first.
+    pub priority: i32,
+}
+
+impl SwapArea {
+    /// The swap file or device at the path.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().into(),
+        }
+    }
+
+    /// Find how much of the swap space is in use, or None if it isn't
+    /// active.
+    ///
+    /// As with [`swaps`], these are the kernel's current numbers, which
+    /// change as pages are swapped in and out. Like the checks before
+    /// writing, the swap space is found by identity rather than by name.
+    pub fn usage(&self) -> Result<Option<SwapUsage>, Error> {
+        let target = std::fs::metadata(&self.path).map_err(Error::OpenDevice)?;
+
+        Ok(find_swap(&target)?.map(|swap| SwapUsage {
+            total: swap.size,
+            used: swap.used,
+            free: swap.size.saturating_sub(swap.used),
+            priority: swap.priority,
+        }))
+    }
+}
+
+/// Check whether the file or device is one of the active swap spaces.
+///
+/// Paths are compared by identity rather than by name, so this works no
+/// matter which symlink or bind mount the target was opened through.
+pub(crate) fn is_active_swap(file: &File) -> Result<bool, Error> {
+    let target = file.metadata().map_err(Error::SizeDetection)?;
+
+    Ok(find_swap(&target)?.is_some())
+}
+
+/// Find the active swap space which is the same file or device as `target`.
+fn find_swap(target: &Metadata) -> Result<Option<SwapEntry>, Error> {
+    let is_block_device = target.file_type().is_block_device();
+
+    for entry in swaps()? {
+        // The swap file may have been deleted or hidden since activation.
+        let Ok(swap) = std::fs::metadata(&entry.path) else {
+            continue;
+        };
+        let same = if is_block_device {
+            swap.file_type().is_block_device() && swap.rdev() == target.rdev()
+        } else {
+            swap.dev() == target.dev() && swap.ino() == target.ino()
+        };
+        if same {
+            return Ok(Some(entry));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find where the block device is mounted, from /proc/self/mountinfo.
+///
+/// Mounts are matched by device number as well as by their source path,
+/// which catches filesystems like btrfs that report an anonymous device
+/// number, and sources named by a symlink like /dev/mapper/root or
+/// /dev/disk/by-uuid. Other files are never considered mounted.
+pub(crate) fn mount_point(file: &File) -> Result<Option<PathBuf>, Error> {
+    let target = file.metadata().map_err(Error::SizeDetection)?;
+    if !target.file_type().is_block_device() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read_to_string("/proc/self/mountinfo").map_err(Error::MountInfo)?;
+    for mount in parse_mountinfo(&contents) {
+        let same_source = || {
+            mount.source.is_absolute()
+                && std::fs::metadata(&mount.source).is_ok_and(|source| {
+                    source.file_type().is_block_device() && source.rdev() == target.rdev()
+                })
+        };
+        if mount.device == (libc::major(target.rdev()), libc::minor(target.rdev())) || same_source()
+        {
+            return Ok(Some(mount.mount_point));
+        }
+    }
+
+    Ok(None)
+}
+
+/// List the partitions of the block device, if it's a whole disk, from sysfs.
+///
+/// Partitions and other files have none. A system without sysfs mounted is
+/// treated as having no partitions.
+pub(crate) fn partitions(file: &File) -> Result<Vec<String>, Error> {
+    let target = file.metadata().map_err(Error::SizeDetection)?;
+    if !target.file_type().is_block_device() {
+        return Ok(Vec::new());
+    }
+
+    let rdev = target.rdev();
+    let dir = format!("/sys/dev/block/{}:{}", libc::major(rdev), libc::minor(rdev));
+    partitions_in(Path::new(&dir)).map_err(Error::Sysfs)
+}
+
+/// List the subdirectories of a sysfs block device directory which are
+/// partitions: those have a `partition` attribute.
+fn partitions_in(dir: &Path) -> std::io::Result<Vec<String>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut partitions = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().join("partition").exists() {
+            partitions.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    partitions.sort();
+    Ok(partitions)
+}
+
+/// The fields of a line of /proc/self/mountinfo used to match mounts.
+#[derive(Debug, PartialEq)]
+struct Mount {
+    device: (u32, u32),
+    mount_point: PathBuf,
+    source: PathBuf,
+}
+
+/// Parse /proc/self/mountinfo, skipping lines which don't parse.
+///
+/// The line format is documented in proc(5): the optional fields end with
+/// a lone `-`, and are followed by the filesystem type and the source.
+fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (major, minor) = fields.nth(2)?.split_once(':')?;
+            let mount_point = fields.nth(1)?;
+            let source = fields.skip_while(|&field| field != "-").nth(2)?;
+            Some(Mount {
+                device: (major.parse().ok()?, minor.parse().ok()?),
+                mount_point: PathBuf::from(unescape(mount_point)),
+                source: PathBuf::from(unescape(source)),
+            })
+        })
+        .collect()
+}
+
+/// Parse /proc/swaps, skipping the heading.
+///
+/// Sizes are listed in KiB. Paths have their whitespace escaped, so the
+/// columns can be split on whitespace.
+fn parse_swaps(contents: &str) -> Vec<SwapEntry> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let path = PathBuf::from(unescape(fields.next()?));
+            let kind = match fields.next()? {
+                "partition" => SwapKind::Partition,
+                "file" => SwapKind::File,
+                other => SwapKind::Other(other.to_string()),
+            };
+            let mut kib = || fields.next()?.parse::<u64>().ok()?.checked_mul(1024);
+            let (size, used) = (kib()?, kib()?);
+            Some(SwapEntry {
+                path,
+                kind,
+                size,
+                used,
+                priority: fields.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Undo the kernel's octal escaping of whitespace and backslashes in paths,
+/// like `\040` for a space.
+pub(crate) fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let escape = rest
+            .get(index + 1..index + 4)
+            .filter(|digits| digits.bytes().all(|b| (b'0'..=b'7').contains(&b)));
+        match escape.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                unescaped.push(char::from(byte));
+                rest = &rest[index + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let contents = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                        /dev/sda2                               partition\t8388604\t\t0\t\t-2\n\
+                        /swap\\040file                           file\t\t1048572\t\t512\t\t-3\n";
+        assert_eq!(
+            parse_swaps(contents),
+            vec![
+                SwapEntry {
+                    path: PathBuf::from("/dev/sda2"),
+                    kind: SwapKind::Partition,
+                    size: 8388604 * 1024,
+                    used: 0,
+                    priority: -2,
+                },
+                SwapEntry {
+                    path: PathBuf::from("/swap file"),
+                    kind: SwapKind::File,
+                    size: 1048572 * 1024,
+                    used: 512 * 1024,
+                    priority: -3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn inactive_usage() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(SwapArea::new(file.path()).usage().unwrap(), None);
+        assert!(matches!(
+            SwapArea::new("/nonexistent").usage(),
+            Err(Error::OpenDevice(_))
+        ));
+    }
+
+    #[test]
+    fn mountinfo() {
+        let contents = "23 28 0:22 / /proc rw,relatime - proc proc rw\n\
+                        28 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+                        40 28 0:35 /@home /home\\040dir rw master:2 - btrfs /dev/mapper/home rw\n\
+                        garbage\n";
+        assert_eq!(
+            parse_mountinfo(contents),
+            vec![
+                Mount {
+                    device: (0, 22),
+                    mount_point: PathBuf::from("/proc"),
+                    source: PathBuf::from("proc"),
+                },
+                Mount {
+                    device: (259, 2),
+                    mount_point: PathBuf::from("/"),
+                    source: PathBuf::from("/dev/nvme0n1p2"),
+                },
+                Mount {
+                    device: (0, 35),
+                    mount_point: PathBuf::from("/home dir"),
+                    source: PathBuf::from("/dev/mapper/home"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sysfs_partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            partitions_in(&dir.path().join("missing")).unwrap(),
+            Vec::<String>::new()
+        );
+
+        for name in ["sda2", "sda1", "queue"] {
+            std::fs::create_dir(dir.path().join(name)).unwrap();
+        }
+        for name in ["sda1", "sda2"] {
+            std::fs::write(dir.path().join(name).join("partition"), "1\n").unwrap();
+        }
+        std::fs::write(dir.path().join("size"), "100\n").unwrap();
+        assert_eq!(partitions_in(dir.path()).unwrap(), vec!["sda1", "sda2"]);
+    }
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape("a\\040b\\011c\\134d"), "a b\tc\\d");
+        assert_eq!(unescape("trailing\\"), "trailing\\");
+        assert_eq!(unescape("short\\04"), "short\\04");
+    }
+}
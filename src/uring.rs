@@ -0,0 +1,275 @@
+//! Erase and scan swap spaces through io_uring, keeping several large reads
+//! or writes in flight at once.
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+
+use crate::erase::filler;
+use crate::{ErasePattern, Error};
+
+/// How many reads or writes are in flight at once.
+const QUEUE_DEPTH: usize = 8;
+
+/// The size of each read or write.
+const CHUNK_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Copy)]
+enum Op {
+    Read,
+    Write,
+}
+
+/// Set up a ring, if the kernel supports io_uring and its reads and writes.
+fn ring() -> Option<IoUring> {
+    let ring = IoUring::new(QUEUE_DEPTH as u32).ok()?;
+    let mut probe = Probe::new();
+    ring.submitter().register_probe(&mut probe).ok()?;
+
+    let supported =
+        probe.is_supported(opcode::Read::CODE) && probe.is_supported(opcode::Write::CODE);
+    supported.then_some(ring)
+}
+
+/// Split `start..end` into chunks of at most `chunk` bytes.
+fn chunks(start: u64, end: u64, chunk: u64) -> impl Iterator<Item = (u64, usize)> {
+    (start..end)
+        .step_by(chunk as usize)
+        .map(move |offset| (offset, (end - offset).min(chunk) as usize))
+}
+
+/// Build the entry running `op` on `buffer` at `offset` of the file, for
+/// the buffer in `slot`.
+fn entry(op: Op, fd: types::Fd, buffer: &mut [u8], offset: u64, slot: usize) -> squeue::Entry {
+    match op {
+        Op::Read => opcode::Read::new(fd, buffer.as_mut_ptr(), buffer.len() as u32)
+            .offset(offset)
+            .build(),
+        Op::Write => opcode::Write::new(fd, buffer.as_ptr(), buffer.len() as u32)
+            .offset(offset)
+            .build(),
+    }
+    .user_data(slot as u64)
+}
+
+/// Run `op` over each chunk of the file, with up to QUEUE_DEPTH in flight.
+/// No chunk may be larger than the first.
+///
+/// Buffers are filled with `fill` before being written. A short write has
+/// the rest of its chunk resubmitted, like `write_all`. `done` is called as
+/// each chunk completes, with its offset, its buffer, and how many bytes
+/// were read or written. Once `done` or the ring fails, nothing new is
+/// submitted, and the error is returned when everything in flight is done.
+fn run(
+    ring: &mut IoUring,
+    file: &File,
+    op: Op,
+    chunks: impl Iterator<Item = (u64, usize)>,
+    mut fill: impl FnMut(&mut [u8]),
+    mut done: impl FnMut(u64, &[u8], std::io::Result<usize>) -> Result<(), Error>,
+    io_error: fn(std::io::Error) -> Error,
+) -> Result<(), Error> {
+    let fd = types::Fd(file.as_raw_fd());
+    // Only the last chunk may be smaller than the first.
+    let mut chunks = chunks.peekable();
+    let chunk = chunks.peek().map_or(0, |&(_, len)| len);
+    let mut buffers = vec![vec![0; chunk]; QUEUE_DEPTH];
+    // The offset, length and bytes written so far of each slot's chunk.
+    let mut pending = [(0, 0, 0); QUEUE_DEPTH];
+    let mut free: Vec<usize> = (0..QUEUE_DEPTH).collect();
+    let mut failed = None;
+
+    loop {
+        while failed.is_none() {
+            let Some(slot) = free.pop() else { break };
+            let Some((offset, len)) = chunks.next() else {
+                free.push(slot);
+                break;
+            };
+            let buffer = &mut buffers[slot][..len];
+            if let Op::Write = op {
+                fill(buffer);
+            }
+            let entry = entry(op, fd, buffer, offset, slot);
+            pending[slot] = (offset, len, 0);
+
+            // SAFETY: the buffer isn't touched, moved or dropped until its
+            // completion has been reaped below, and the ring has room for
+            // every slot.
+            unsafe { ring.submission().push(&entry) }.expect("the submission queue is full");
+        }
+
+        let in_flight = QUEUE_DEPTH - free.len();
+        if in_flight == 0 {
+            return failed.map_or(Ok(()), Err);
+        }
+        match ring.submit_and_wait(1) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // The kernel may still be using the buffers, so leak them.
+                std::mem::forget(buffers);
+                return Err(io_error(e));
+            }
+        }
+
+        let completed: Vec<(usize, i32)> = ring
+            .completion()
+            .map(|entry| (entry.user_data() as usize, entry.result()))
+            .collect();
+        for (slot, result) in completed {
+            let (offset, len, written) = pending[slot];
+            if let (Op::Write, None, Ok(n @ 1..)) = (op, &failed, usize::try_from(result)) {
+                if written + n < len {
+                    let written = written + n;
+                    pending[slot].2 = written;
+                    let buffer = &mut buffers[slot][written..len];
+                    let entry = entry(op, fd, buffer, offset + written as u64, slot);
+                    // SAFETY: as above, and this slot's entry has completed,
+                    // so there's room for it again.
+                    unsafe { ring.submission().push(&entry) }
+                        .expect("the submission queue is full");
+                    continue;
+                }
+            }
+            free.push(slot);
+            if failed.is_some() {
+                continue;
+            }
+            let result = match result {
+                n if n < 0 => Err(std::io::Error::from_raw_os_error(-n)),
+                n => Ok(written + n as usize),
+            };
+            if let Err(e) = done(offset, &buffers[slot][..len], result) {
+                failed = Some(e);
+            }
+        }
+    }
+}
+
+/// Overwrite `start..size` of the file with the pattern, as
+/// [`erase_from`](crate::erase::erase_from) does.
+///
+/// Returns false without writing anything if io_uring isn't available.
+pub(crate) fn erase(
+    file: &File,
+    start: u64,
+    size: u64,
+    pattern: ErasePattern,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<bool, Error> {
+    let Some(mut ring) = ring() else {
+        return Ok(false);
+    };
+
+    let mut written = start;
+    run(
+        &mut ring,
+        file,
+        Op::Write,
+        chunks(start, size, CHUNK_BYTES),
+        filler(pattern),
+        |_, buffer, result| {
+            match result {
+                Ok(n) if n == buffer.len() => {}
+                // Short writes are resubmitted, so this one made no progress.
+                Ok(_) => return Err(Error::Erase(std::io::ErrorKind::WriteZero.into())),
+                Err(e) => return Err(Error::Erase(e)),
+            }
+            written += buffer.len() as u64;
+            progress(written, size)
+        },
+        Error::Erase,
+    )?;
+
+    Ok(true)
+}
+
+/// Read every page after the header, returning the pages which couldn't be
+/// read, as [`scan::read_only`](crate::scan::read_only) does. The swap space
+/// starts at `offset` in the file.
+///
+/// Returns None without reading anything if io_uring isn't available.
+pub(crate) fn read_only(
+    file: &File,
+    offset: u64,
+    page_size: u64,
+    pages: u32,
+    mut progress: impl FnMut(u64, u64) -> Result<(), Error>,
+) -> Result<Option<Vec<u32>>, Error> {
+    let Some(mut ring) = ring() else {
+        return Ok(None);
+    };
+
+    let total = u64::from(pages) * page_size;
+    let chunk = (CHUNK_BYTES / page_size).max(1) * page_size;
+    let mut page = vec![0; page_size as usize];
+    let mut bad_pages = Vec::new();
+    let mut read = page_size;
+    run(
+        &mut ring,
+        file,
+        Op::Read,
+        chunks(offset + page_size, offset + total, chunk),
+        |_| {},
+        |start, buffer, result| {
+            if !matches!(result, Ok(n) if n == buffer.len()) {
+                // Find out which of the chunk's pages are unreadable.
+                for position in (start..start + buffer.len() as u64).step_by(page_size as usize) {
+                    if file.read_exact_at(&mut page, position).is_err() {
+                        bad_pages.push(((position - offset) / page_size) as u32);
+                    }
+                }
+            }
+            read += buffer.len() as u64;
+            progress(read, total)
+        },
+        Error::Scan,
+    )?;
+    bad_pages.sort_unstable();
+
+    Ok(Some(bad_pages))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn erase_and_scan() {
+        let file = tempfile::tempfile().unwrap();
+        file.write_all_at(&vec![0xff; 3 * 1024 * 1024], 0).unwrap();
+
+        let mut reports = Vec::new();
+        let erased = erase(
+            &file,
+            4096,
+            3 * 1024 * 1024,
+            ErasePattern::Zeros,
+            |done, total| {
+                reports.push((done, total));
+                Ok(())
+            },
+        )
+        .unwrap();
+        if !erased {
+            // This kernel doesn't support io_uring.
+            return;
+        }
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last(), Some(&(3 * 1024 * 1024, 3 * 1024 * 1024)));
+
+        let mut contents = vec![0; 3 * 1024 * 1024];
+        file.read_exact_at(&mut contents, 0).unwrap();
+        assert!(contents[..4096].iter().all(|&b| b == 0xff));
+        assert!(contents[4096..].iter().all(|&b| b == 0));
+
+        let found = read_only(&file, 4096, 4096, 767, |_, _| Ok(())).unwrap();
+        assert_eq!(found, Some(vec![]));
+        // Pages past the end of the file can't be read.
+        let found = read_only(&file, 4096, 4096, 770, |_, _| Ok(())).unwrap();
+        assert_eq!(found, Some(vec![767, 768, 769]));
+    }
+}
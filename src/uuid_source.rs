@@ -0,0 +1,250 @@
+//! Generators of swap space UUIDs, for when random ones aren't wanted.
+
+use core::str::FromStr;
+
+use alloc::string::ToString;
+use uuid::Uuid;
+
+use crate::{Error, SwapWriter};
+
+/// A generator of UUIDs, given to [`SwapWriter::uuid_source`].
+pub trait UuidSource {
+    /// Generate the next UUID.
+    fn next_uuid(&mut self) -> Uuid;
+}
+
+/// Random version 4 UUIDs, as generated when no UUID is specified.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomUuids;
+
+impl UuidSource for RandomUuids {
+    fn next_uuid(&mut self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// The same UUID every time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedUuid(pub Uuid);
+
+impl UuidSource for FixedUuid {
+    fn next_uuid(&mut self) -> Uuid {
+        self.0
+    }
+}
+
+/// Version 4 UUIDs from a pseudorandom sequence, which is the same for the
+/// same seed.
+///
+/// The sequence is predictable, so these UUIDs are only unique among those
+/// from different seeds.
+#[derive(Debug, Clone)]
+pub struct SeededUuids {
+    state: u64,
+}
+
+impl SeededUuids {
+    /// Construct a new SeededUuids, starting the sequence of the seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// The next number of the sequence, by splitmix64.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl UuidSource for SeededUuids {
+    fn next_uuid(&mut self) -> Uuid {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_le_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_le_bytes());
+        uuid::Builder::from_random_bytes(bytes).into_uuid()
+    }
+}
+
+/// Consecutive UUIDs, counting up from the first, like
+/// `00000000-0000-0000-0000-000000000001` and then `…0002`. These aren't
+/// valid UUIDs of any version, but are easy to tell apart in tests.
+#[derive(Debug, Clone)]
+pub struct CounterUuids {
+    next: u128,
+}
+
+impl CounterUuids {
+    /// Construct a new CounterUuids, starting at `first`.
+    pub fn new(first: Uuid) -> Self {
+        Self {
+            next: first.as_u128(),
+        }
+    }
+}
+
+impl UuidSource for CounterUuids {
+    fn next_uuid(&mut self) -> Uuid {
+        let uuid = Uuid::from_u128(self.next);
+        self.next = self.next.wrapping_add(1);
+        uuid
+    }
+}
+
+/// A UUID as given to util-linux's `mkswap --uuid`: `clear`, `random`,
+/// `time`, or a UUID.
+///
+/// As a [`UuidSource`], it generates a new UUID of its kind each time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UuidSpec {
+    /// The nil UUID, which blkid and udev treat as having no UUID.
+    Clear,
+    /// A random version 4 UUID.
+    Random,
+    /// A time-based version 1 UUID, with a random node ID.
+    #[cfg(feature = "std")]
+    Time,
+    /// A time-ordered version 7 UUID, which sorts by when it was generated
+    /// and encodes the time in milliseconds. util-linux has no keyword for
+    /// it.
+    #[cfg(feature = "std")]
+    TimeOrdered,
+    /// This UUID.
+    Uuid(Uuid),
+}
+
+impl UuidSource for UuidSpec {
+    fn next_uuid(&mut self) -> Uuid {
+        match *self {
+            UuidSpec::Clear => Uuid::nil(),
+            UuidSpec::Random => Uuid::new_v4(),
+            #[cfg(feature = "std")]
+            UuidSpec::Time => {
+                // A random node ID has the multicast bit set, so it can't
+                // collide with a real MAC address.
+                let mut node_id = [0; 6];
+                node_id.copy_from_slice(&Uuid::new_v4().as_bytes()[..6]);
+                node_id[0] |= 0x01;
+                Uuid::now_v1(&node_id)
+            }
+            #[cfg(feature = "std")]
+            UuidSpec::TimeOrdered => Uuid::now_v7(),
+            UuidSpec::Uuid(uuid) => uuid,
+        }
+    }
+}
+
+impl FromStr for UuidSpec {
+    type Err = Error;
+
+    /// Parse `clear`, `random`, `time`, or a UUID in any of the formats of
+    /// [`Uuid::parse_str`].
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        match spec {
+            "clear" => Ok(UuidSpec::Clear),
+            "random" => Ok(UuidSpec::Random),
+            #[cfg(feature = "std")]
+            "time" => Ok(UuidSpec::Time),
+            uuid => Uuid::parse_str(uuid)
+                .map(UuidSpec::Uuid)
+                .map_err(|_| Error::InvalidUuid(spec.to_string())),
+        }
+    }
+}
+
+impl SwapWriter {
+    /// Specify the filesystem's UUID as the next one from the source.
+    ///
+    /// The UUID is taken from the source immediately, so one source can be
+    /// shared by several writers, like those of an image's swap spaces.
+    pub fn uuid_source<S: UuidSource + ?Sized>(self, source: &mut S) -> Self {
+        self.uuid(source.next_uuid())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sources() {
+        let uuid = Uuid::from_u128(0x1234);
+        assert_eq!(FixedUuid(uuid).next_uuid(), uuid);
+        assert_eq!(RandomUuids.next_uuid().get_version_num(), 4);
+
+        let mut counter = CounterUuids::new(Uuid::from_u128(1));
+        assert_eq!(counter.next_uuid(), Uuid::from_u128(1));
+        assert_eq!(counter.next_uuid(), Uuid::from_u128(2));
+
+        let mut seeded = SeededUuids::new(42);
+        let first = seeded.next_uuid();
+        assert_eq!(first.get_version_num(), 4);
+        assert_ne!(seeded.next_uuid(), first);
+        assert_eq!(SeededUuids::new(42).next_uuid(), first);
+        assert_ne!(SeededUuids::new(43).next_uuid(), first);
+
+        let mut counter = CounterUuids::new(Uuid::from_u128(7));
+        let (_, summary) = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 4096)
+            .uuid_source(&mut counter)
+            .build_header()
+            .unwrap();
+        assert_eq!(summary.uuid, Some(Uuid::from_u128(7)));
+        let source: &mut dyn UuidSource = &mut counter;
+        let (_, summary) = SwapWriter::new()
+            .page_size(4096)
+            .size(40 * 4096)
+            .uuid_source(source)
+            .build_header()
+            .unwrap();
+        assert_eq!(summary.uuid, Some(Uuid::from_u128(8)));
+    }
+
+    #[test]
+    fn uuid_spec() {
+        let uuid = "0123abcd-0000-4000-8000-000000000000";
+        assert_eq!(
+            uuid.parse::<UuidSpec>().unwrap(),
+            UuidSpec::Uuid(Uuid::parse_str(uuid).unwrap())
+        );
+        assert_eq!(
+            "clear".parse::<UuidSpec>().unwrap().next_uuid(),
+            Uuid::nil()
+        );
+        assert_eq!(
+            "random"
+                .parse::<UuidSpec>()
+                .unwrap()
+                .next_uuid()
+                .get_version_num(),
+            4
+        );
+        #[cfg(feature = "std")]
+        {
+            let mut time: UuidSpec = "time".parse().unwrap();
+            let uuid = time.next_uuid();
+            assert_eq!(uuid.get_version_num(), 1);
+            assert_eq!(uuid.as_bytes()[10] & 0x01, 0x01);
+            assert_ne!(time.next_uuid(), uuid);
+
+            let mut ordered = UuidSpec::TimeOrdered;
+            let first = ordered.next_uuid();
+            assert_eq!(first.get_version_num(), 7);
+            assert!(ordered.next_uuid() > first);
+            let (_, summary) = SwapWriter::new()
+                .page_size(4096)
+                .size(40 * 4096)
+                .uuid_v7()
+                .build_header()
+                .unwrap();
+            assert!(summary.uuid.unwrap() > first);
+        }
+        assert!(matches!(
+            "tomorrow".parse::<UuidSpec>(),
+            Err(Error::InvalidUuid(spec)) if spec == "tomorrow"
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+//! Conditions which don't fail an operation, but which the caller should
+//! know about.
+
+use core::fmt;
+
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+use crate::Arch;
+#[cfg(feature = "std")]
+use crate::FoundSignature;
+
+/// Something surprising which was done or found while writing a swap space,
+/// collected into [`WriteSummary::warnings`](crate::WriteSummary::warnings).
+///
+/// The [`Display`](fmt::Display) form is a sentence fragment for logs, like
+/// `filled the holes of a sparse file with zeros`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The page size which was set differs from this system's, so the swap
+    /// space can't be used here without `swapon --fixpgsz`.
+    PageSizeMismatch {
+        /// The page size the swap space was written for.
+        page_size: u64,
+        /// The page size of this system.
+        host: u64,
+    },
+    /// The swap space was clamped to the most pages the header can describe,
+    /// by [`ClampPolicy::ClampWithWarning`](crate::ClampPolicy::ClampWithWarning).
+    Clamped {
+        /// The number of pages which were written.
+        pages: u32,
+        /// The number of pages which fit in the size.
+        fit: u64,
+    },
+    /// The kernel of the architecture can only use part of the swap space,
+    /// by [`ArchLimitPolicy::Warn`](crate::ArchLimitPolicy::Warn).
+    ExceedsArchLimit {
+        /// The architecture checked against.
+        arch: Arch,
+        /// The most bytes its kernel can use.
+        max: u64,
+        /// The size of the swap space in bytes.
+        size: u64,
+    },
+    /// The label was truncated at a character boundary to fit, by
+    /// [`SwapWriter::label_lossy`](crate::SwapWriter::label_lossy).
+    LabelTruncated {
+        /// The length of the label in bytes.
+        length: usize,
+        /// How many of its bytes were kept.
+        kept: usize,
+    },
+    /// The holes of a sparse file were filled with zeros.
+    FilledHoles,
+    /// An old signature was wiped.
+    #[cfg(feature = "std")]
+    WipedSignature(FoundSignature),
+    /// The file can be read or written by users other than its owner, who
+    /// could read the swapped out memory. The attached value is its mode.
+    InsecurePermissions(u32),
+    /// The GPT partition's type isn't Linux swap, so it won't be discovered
+    /// as swap. The attached value is its type.
+    #[cfg(feature = "uuid")]
+    PartitionTypeNotSwap(Uuid),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::PageSizeMismatch { page_size, host } => write!(
+                f,
+                "the page size {} differs from this system's {}: the swap space can't be used here without swapon --fixpgsz",
+                page_size, host
+            ),
+            Warning::Clamped { pages, fit } => write!(
+                f,
+                "clamped the swap space to {} pages, of the {} which fit in its size",
+                pages, fit
+            ),
+            Warning::ExceedsArchLimit { arch, max, size } => write!(
+                f,
+                "the {} kernel can only use {} of the {} bytes of the swap space",
+                arch.name(),
+                max,
+                size
+            ),
+            Warning::LabelTruncated { length, kept } => write!(
+                f,
+                "truncated the label from {} to {} bytes",
+                length, kept
+            ),
+            Warning::FilledHoles => write!(f, "filled the holes of a sparse file with zeros"),
+            #[cfg(feature = "std")]
+            Warning::WipedSignature(found) => write!(
+                f,
+                "wiped an old {} signature at offset {}",
+                found.signature.name(),
+                found.offset
+            ),
+            Warning::InsecurePermissions(mode) => write!(
+                f,
+                "insecure permissions {:04o}, fix with: chmod 0600",
+                mode
+            ),
+            #[cfg(feature = "uuid")]
+            Warning::PartitionTypeNotSwap(guid) => {
+                write!(f, "the partition's type {} isn't Linux swap", guid)
+            }
+        }
+    }
+}
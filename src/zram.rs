@@ -0,0 +1,167 @@
+//! Provision compressed swap in RAM on a zram device, through sysfs.
+
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+
+#[cfg(feature = "swapon")]
+use crate::{swapon, SwapOnOptions};
+use crate::{Error, SwapWriter, WriteSummary};
+
+/// A builder for a swap space on a zram device.
+///
+/// The zram module must already be loaded, for example with
+/// `modprobe zram num_devices=0`.
+#[derive(Debug, Clone)]
+pub struct ZramSwap {
+    disksize: u64,
+    device: Option<u32>,
+    algorithm: Option<String>,
+    streams: Option<u32>,
+    #[cfg(feature = "swapon")]
+    activate: Option<SwapOnOptions>,
+    sysfs: PathBuf,
+    dev: PathBuf,
+}
+
+/// What [`ZramSwap::setup`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZramSummary {
+    /// The zram device, like `/dev/zram0`.
+    pub device: PathBuf,
+    /// What was written to the device.
+    pub swap: WriteSummary,
+}
+
+impl ZramSwap {
+    /// Construct a new ZramSwap, which holds up to `disksize` bytes before
+    /// compression.
+    pub fn new(disksize: u64) -> Self {
+        Self {
+            disksize,
+            device: None,
+            algorithm: None,
+            streams: None,
+            #[cfg(feature = "swapon")]
+            activate: None,
+            sysfs: PathBuf::from("/sys"),
+            dev: PathBuf::from("/dev"),
+        }
+    }
+
+    /// Use the existing, unconfigured `/dev/zram<device>`. Otherwise, a new
+    /// device is added with `/sys/class/zram-control/hot_add`.
+    pub fn device(mut self, device: u32) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Set the compression algorithm, like `zstd` or `lz4`. The kernel's
+    /// default is used if none is set.
+    pub fn algorithm(mut self, algorithm: &str) -> Self {
+        self.algorithm = Some(algorithm.to_string());
+        self
+    }
+
+    /// Set how many compression streams may run at once. Since Linux 4.7
+    /// there is one per CPU, and this is ignored.
+    pub fn streams(mut self, streams: u32) -> Self {
+        self.streams = Some(streams);
+        self
+    }
+
+    /// Activate the swap space with these options once it's written.
+    #[cfg(feature = "swapon")]
+    pub fn activate(mut self, options: SwapOnOptions) -> Self {
+        self.activate = Some(options);
+        self
+    }
+
+    /// Configure the zram device, and write the swap space to it with
+    /// `writer`. This needs root.
+    pub fn setup(self, writer: SwapWriter) -> Result<ZramSummary, Error> {
+        let id = match self.device {
+            Some(id) => id,
+            None => self.hot_add()?,
+        };
+
+        let block = self.sysfs.join(format!("block/zram{}", id));
+        let set = |attribute: &str, value: String| {
+            std::fs::write(block.join(attribute), value).map_err(Error::Zram)
+        };
+        // The algorithm and streams can only be changed before the disksize
+        // is set, which initializes the device.
+        if let Some(algorithm) = &self.algorithm {
+            set("comp_algorithm", algorithm.clone())?;
+        }
+        if let Some(streams) = self.streams {
+            set("max_comp_streams", streams.to_string())?;
+        }
+        set("disksize", self.disksize.to_string())?;
+
+        let device = self.dev.join(format!("zram{}", id));
+        let mut handle = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&device)
+            .map_err(Error::OpenDevice)?;
+        let swap = writer.size(self.disksize).write(&mut handle)?;
+        drop(handle);
+
+        #[cfg(feature = "swapon")]
+        if let Some(options) = &self.activate {
+            swapon(&device, options)?;
+        }
+
+        Ok(ZramSummary { device, swap })
+    }
+
+    /// Add a zram device, returning its number.
+    fn hot_add(&self) -> Result<u32, Error> {
+        let id = std::fs::read_to_string(self.sysfs.join("class/zram-control/hot_add"))
+            .map_err(Error::Zram)?;
+        id.trim().parse().map_err(|_| {
+            Error::Zram(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "hot_add returned an invalid device number",
+            ))
+        })
+    }
+
+    /// Use other directories in place of /sys and /dev.
+    #[cfg(test)]
+    fn roots(mut self, sysfs: PathBuf, dev: PathBuf) -> Self {
+        self.sysfs = sysfs;
+        self.dev = dev;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn setup() {
+        let root = tempfile::tempdir().unwrap();
+        let sysfs = root.path().join("sys");
+        std::fs::create_dir_all(sysfs.join("class/zram-control")).unwrap();
+        std::fs::create_dir_all(sysfs.join("block/zram3")).unwrap();
+        std::fs::write(sysfs.join("class/zram-control/hot_add"), "3\n").unwrap();
+        std::fs::write(root.path().join("zram3"), []).unwrap();
+
+        let summary = ZramSwap::new(1024 * 1024)
+            .algorithm("zstd")
+            .roots(sysfs.clone(), root.path().into())
+            .setup(SwapWriter::new().page_size(4096))
+            .unwrap();
+        assert_eq!(summary.device, root.path().join("zram3"));
+        assert_eq!(summary.swap.pages, 256);
+
+        let read = |attribute| std::fs::read_to_string(sysfs.join("block/zram3").join(attribute));
+        assert_eq!(read("comp_algorithm").unwrap(), "zstd");
+        assert_eq!(read("disksize").unwrap(), "1048576");
+        assert!(read("max_comp_streams").is_err());
+        let written = std::fs::read(&summary.device).unwrap();
+        assert_eq!(&written[4096 - 10..4096], b"SWAPSPACE2");
+    }
+}
@@ -0,0 +1,183 @@
+//! Read and configure zswap, the compressed cache in front of swap devices,
+//! through `/sys/module/zswap/parameters`.
+
+use std::path::Path;
+
+use crate::Error;
+
+const PARAMETERS: &str = "/sys/module/zswap/parameters";
+
+/// The current zswap parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZswapParameters {
+    /// Whether pages are compressed into the pool before being swapped out.
+    pub enabled: bool,
+    /// The compression algorithm, like `zstd` or `lzo`.
+    pub compressor: String,
+    /// The allocator of the pool, like `zsmalloc`. None on kernels which
+    /// only have one.
+    pub zpool: Option<String>,
+    /// The most RAM the pool may use, as a percentage.
+    pub max_pool_percent: u8,
+}
+
+/// Read the zswap parameters of the running kernel.
+pub fn zswap_parameters() -> Result<ZswapParameters, Error> {
+    read_parameters(Path::new(PARAMETERS))
+}
+
+fn read_parameters(directory: &Path) -> Result<ZswapParameters, Error> {
+    let read = |name: &str| {
+        std::fs::read_to_string(directory.join(name))
+            .map(|value| value.trim_end().to_string())
+            .map_err(Error::Zswap)
+    };
+    let invalid = |name: &str| {
+        Error::Zswap(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("zswap's {} parameter is invalid", name),
+        ))
+    };
+
+    let enabled = match read("enabled")?.as_str() {
+        "Y" | "1" => true,
+        "N" | "0" => false,
+        _ => return Err(invalid("enabled")),
+    };
+    let zpool = match read("zpool") {
+        Ok(zpool) => Some(zpool),
+        Err(Error::Zswap(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    Ok(ZswapParameters {
+        enabled,
+        compressor: read("compressor")?,
+        zpool,
+        max_pool_percent: read("max_pool_percent")?
+            .parse()
+            .map_err(|_| invalid("max_pool_percent"))?,
+    })
+}
+
+/// A builder for changes to the zswap parameters.
+///
+/// Parameters which aren't set are left as they are.
+#[derive(Debug, Clone, Default)]
+pub struct ZswapConfig {
+    enabled: Option<bool>,
+    compressor: Option<String>,
+    zpool: Option<String>,
+    max_pool_percent: Option<u8>,
+}
+
+impl ZswapConfig {
+    /// Construct a new ZswapConfig with all-default Nones
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable zswap.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    /// Set the compression algorithm, which the kernel must have.
+    pub fn compressor(mut self, compressor: &str) -> Self {
+        self.compressor = Some(compressor.to_string());
+        self
+    }
+
+    /// Set the allocator of the pool.
+    pub fn zpool(mut self, zpool: &str) -> Self {
+        self.zpool = Some(zpool.to_string());
+        self
+    }
+
+    /// Set the most RAM the pool may use, as a percentage.
+    ///
+    /// Must be at most 100, or an error is returned.
+    pub fn max_pool_percent(mut self, percent: u8) -> Result<Self, Error> {
+        if percent > 100 {
+            return Err(Error::InvalidPoolPercent(percent));
+        }
+
+        self.max_pool_percent = Some(percent);
+        Ok(self)
+    }
+
+    /// Write the parameters to the running kernel. This needs root.
+    pub fn apply(&self) -> Result<(), Error> {
+        self.apply_to(Path::new(PARAMETERS))
+    }
+
+    fn apply_to(&self, directory: &Path) -> Result<(), Error> {
+        let write = |name: &str, value: String| {
+            std::fs::write(directory.join(name), value).map_err(Error::Zswap)
+        };
+
+        if let Some(compressor) = &self.compressor {
+            write("compressor", compressor.clone())?;
+        }
+        if let Some(zpool) = &self.zpool {
+            write("zpool", zpool.clone())?;
+        }
+        if let Some(percent) = self.max_pool_percent {
+            write("max_pool_percent", percent.to_string())?;
+        }
+        // Enable zswap last, so it starts with the new pool.
+        if let Some(enabled) = self.enabled {
+            write("enabled", String::from(if enabled { "Y" } else { "N" }))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parameters() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+        for (name, value) in [
+            ("enabled", "N\n"),
+            ("compressor", "lzo\n"),
+            ("max_pool_percent", "20\n"),
+        ] {
+            std::fs::write(directory.join(name), value).unwrap();
+        }
+        let mut parameters = read_parameters(directory).unwrap();
+        assert_eq!(
+            parameters,
+            ZswapParameters {
+                enabled: false,
+                compressor: String::from("lzo"),
+                zpool: None,
+                max_pool_percent: 20,
+            }
+        );
+
+        ZswapConfig::new()
+            .enabled(true)
+            .compressor("zstd")
+            .zpool("zsmalloc")
+            .max_pool_percent(25)
+            .unwrap()
+            .apply_to(directory)
+            .unwrap();
+        parameters = read_parameters(directory).unwrap();
+        assert!(parameters.enabled);
+        assert_eq!(parameters.compressor, "zstd");
+        assert_eq!(parameters.zpool.as_deref(), Some("zsmalloc"));
+        assert_eq!(parameters.max_pool_percent, 25);
+
+        assert!(matches!(
+            ZswapConfig::new().max_pool_percent(101),
+            Err(Error::InvalidPoolPercent(101))
+        ));
+    }
+}